@@ -0,0 +1,407 @@
+use crate::s;
+use crate::utils::*;
+
+/// Milestone tree: c1 exponent and n term exponent.
+pub const MILESTONES: Milestones<2> = Milestones {
+    points: &[10., 20., 30., 40., 60.],
+    max_levels: [2, 3],
+    order: [0, 1],
+};
+
+/// log10 of 1 / (pi^2/6 - sum_{k=1}^{n} 1/k^2), the n-term of the Basel series.
+/// Summed exactly for small n, asymptotic expansion of the tail otherwise.
+fn basel_term(n: u32) -> f64 {
+    let n = n.max(1);
+    let tail: f64 = if n < 20 {
+        std::f64::consts::PI.powi(2) / 6. - (1..=n).map(|k| 1. / (k as f64).powi(2)).sum::<f64>()
+    } else {
+        let n = n as f64;
+        1. / n - 1. / (2. * n.powi(2)) + 1. / (6. * n.powi(3)) - 1. / (30. * n.powi(5))
+    };
+    -tail.log10()
+}
+
+struct BaPvars {
+    tdot: Variable<ExponentialCost, LinearValue>,
+    c1: Variable<FirstFreeCost<ExponentialCost>, StepwiseValue>,
+    c2: Variable<ExponentialCost, ExponentialValue>,
+    a: Variable<CompositeCost<ExponentialCost, ExponentialCost>, StepwiseValue>,
+    n: Variable<CompositeCost<ExponentialCost, ExponentialCost>, LinearValue>,
+}
+
+impl BaPvars {
+    fn init() -> Self {
+        BaPvars {
+            tdot: Variable::new(ExponentialCost::new(1e6, 1e6), LinearValue::new(0.2, 0.2)),
+            c1: Variable::new(
+                FirstFreeCost {
+                    model: ExponentialCost::new(10., 1.8),
+                },
+                StepwiseValue::new(2., 10),
+            ),
+            c2: Variable::new(ExponentialCost::new(50., 6.), ExponentialValue::new(2.)),
+            a: Variable::new(
+                CompositeCost {
+                    model1: ExponentialCost::new(1e10, 12.),
+                    model2: ExponentialCost::new(1e80, 40.),
+                    cutoff: 40,
+                },
+                StepwiseValue::new(2., 10),
+            ),
+            n: Variable::new(
+                CompositeCost {
+                    model1: ExponentialCost::new(1e20, 1e3),
+                    model2: ExponentialCost::new(1e120, 1e4),
+                    cutoff: 30,
+                },
+                LinearValue::new(1., 1.),
+            ),
+        }
+    }
+
+    fn getm(&mut self, id: usize) -> &mut dyn VariableTrait {
+        match id {
+            0 => &mut self.tdot,
+            1 => &mut self.c1,
+            2 => &mut self.c2,
+            3 => &mut self.a,
+            _ => &mut self.n,
+        }
+    }
+
+    fn get(&self, id: usize) -> &dyn VariableTrait {
+        match id {
+            0 => &self.tdot,
+            1 => &self.c1,
+            2 => &self.c2,
+            3 => &self.a,
+            _ => &self.n,
+        }
+    }
+
+    fn set(&mut self, lvls: [u32; 5]) {
+        for (i, level) in lvls.iter().enumerate() {
+            self.getm(i).set(*level);
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct BaPdata {
+    caps: [u32; 5],
+    /// Milestone levels, in `MILESTONES` order.
+    pub milestones: [u32; 2],
+    /// Derive the milestones from rho and tau instead of keeping the given ones.
+    pub derive_milestones: bool,
+    pub do_coasting: bool,
+}
+
+pub struct BaPstate {
+    pub levels: [u32; 5],
+    pub tvar: f64,
+    pub q: f64,
+    pub r: f64,
+}
+
+pub struct BaP {
+    data: TheoryData,
+    pub bapdata: BaPdata,
+    pub goal: f64,
+    rho: f64,
+    maxrho: f64,
+    multiplier: f64,
+    tvar: f64,
+    q: f64,
+    r: f64,
+    nterm: f64,
+    vars: BaPvars,
+    varbuys: Vec<VarBuy>,
+
+    t: f64,
+    dt: f64,
+    ddt: f64,
+    depth: u32,
+
+    best_res: SimRes,
+}
+
+impl BaP {
+    pub fn new(data: TheoryData, goal: f64, state: Option<BaPstate>) -> Self {
+        let mut bap: BaP = BaP {
+            data,
+            bapdata: BaPdata {
+                caps: [u32::MAX; 5],
+                milestones: MILESTONES.max_levels,
+                derive_milestones: true,
+                do_coasting: true,
+            },
+            goal,
+            rho: 0.,
+            maxrho: 0.,
+            multiplier: 0.,
+            tvar: 0.,
+            q: 0.,
+            r: 0.,
+            nterm: 0.,
+            vars: BaPvars::init(),
+            varbuys: Vec::new(),
+
+            t: 0.,
+            dt: 1.5,
+            ddt: 1.0001,
+            depth: 0,
+
+            best_res: SimRes::default(),
+        };
+
+        if let Some(state) = state {
+            bap.vars.set(state.levels);
+            bap.tvar = state.tvar;
+            bap.q = state.q;
+            bap.r = state.r;
+        }
+
+        bap.rho = bap.data.rho;
+        bap.multiplier = bap.get_multiplier(bap.data.tau);
+        bap.nterm = basel_term(bap.vars.n.value as u32);
+
+        bap
+    }
+
+    pub fn fork(&self) -> Self {
+        let mut new: BaP = BaP {
+            data: self.data,
+            bapdata: self.bapdata,
+            goal: self.goal,
+            rho: self.rho,
+            maxrho: self.maxrho,
+            multiplier: self.multiplier,
+            tvar: self.tvar,
+            q: self.q,
+            r: self.r,
+            nterm: self.nterm,
+            vars: BaPvars::init(),
+            varbuys: self.varbuys.clone(),
+            t: self.t,
+            dt: self.dt,
+            ddt: self.ddt,
+            depth: self.depth + 1,
+
+            best_res: SimRes::default(),
+        };
+
+        for i in 0..5 {
+            new.vars.getm(i).set(self.vars.get(i).get_level())
+        }
+
+        new
+    }
+
+    /// Milestones reached with the max of rho and the last pub's rho.
+    fn update_milestones(&mut self) {
+        if self.bapdata.derive_milestones {
+            let rho = self.maxrho.max(self.data.tau * 2.5);
+            self.bapdata.milestones = MILESTONES.at(rho);
+        }
+    }
+
+    fn get_multiplier(&self, tau: f64) -> f64 {
+        tau * 1.1388 - 16.
+    }
+
+    /// Exponent on the n-term, raised by the n milestone.
+    fn get_nterm_exponent(&self) -> f64 {
+        1. + 0.5 * self.bapdata.milestones[1] as f64
+    }
+
+    fn get_variable_conditions(&self, id: usize) -> bool {
+        match id {
+            0 => self.vars.tdot.level < 4,
+            1..=4 => true,
+            _ => false,
+        }
+    }
+
+    fn eval_coast_one(&self, dist: f64, lbound: f64, ubound: f64) -> BuyEval {
+        if dist > ubound {
+            BuyEval::BUY
+        } else if dist > lbound {
+            BuyEval::FORK
+        } else {
+            BuyEval::SKIP
+        }
+    }
+
+    fn eval_coast(&self, id: usize, cost: f64) -> BuyEval {
+        let dist: f64 = self.goal - cost;
+        if dist > 3. || !self.bapdata.do_coasting {
+            return BuyEval::BUY;
+        }
+        match id {
+            0 => BuyEval::BUY,
+            1 => self.eval_coast_one(dist, 0.5, 1.5),
+            2 => self.eval_coast_one(dist, 0.15, 0.6),
+            3 => self.eval_coast_one(dist, 0.5, 1.5),
+            4 => self.eval_coast_one(dist, 1., 2.5),
+            _ => BuyEval::SKIP,
+        }
+    }
+
+    fn eval_ratio(&self, id: usize) -> BuyEval {
+        if match id {
+            0 => true,
+            1 => {
+                self.vars.c1.cost + (7. + (self.vars.c1.level % 10) as f64).log10()
+                    < self.vars.c2.cost
+            }
+            2 => true,
+            3 => {
+                self.vars.a.cost + (4. + (self.vars.a.level % 10) as f64).log10()
+                    < self.vars.c2.cost
+            }
+            4 => {
+                // n only adds a polynomial factor, buy it when it beats c2 per unit of rho
+                let gain: f64 = self.get_nterm_exponent()
+                    * (basel_term(self.vars.n.value as u32 + 1) - self.nterm);
+                gain.log10() - self.vars.n.cost >= 2f64.log10().log10() - self.vars.c2.cost
+            }
+            _ => false,
+        } {
+            BuyEval::BUY
+        } else {
+            BuyEval::SKIP
+        }
+    }
+
+    fn tick(&mut self) {
+        let logdt: f64 = self.dt.log10();
+
+        self.tvar += self.dt * self.vars.tdot.value;
+        self.q = log10add(
+            self.q,
+            self.vars.c1.value * (1. + 0.05 * self.bapdata.milestones[0] as f64)
+                + self.vars.c2.value
+                + self.tvar.log10()
+                + logdt,
+        );
+        self.r = log10add(
+            self.r,
+            self.vars.a.value + self.nterm * self.get_nterm_exponent() + logdt,
+        );
+        self.rho = log10add(self.rho, self.multiplier + self.q + self.r + logdt);
+
+        self.maxrho = self.maxrho.max(self.rho);
+
+        self.t += self.dt / 1.5;
+        self.dt *= self.ddt;
+    }
+
+    pub fn simulate(&mut self) -> SimRes {
+        while self.maxrho < self.goal {
+            self.update_milestones();
+            self.tick();
+            self.buy();
+        }
+
+        if self.t < self.best_res.t {
+            SimRes {
+                t: self.t,
+                var_buys: Some(self.varbuys.clone()),
+            }
+        } else {
+            SimRes {
+                t: self.best_res.t,
+                var_buys: self.best_res.var_buys.clone(),
+            }
+        }
+    }
+
+    fn buy(&mut self) {
+        let mut cost: f64;
+        let mut coast_eval: BuyEval;
+        let mut ratio_eval: BuyEval;
+        let names = ["tdot", "c1", "c2", "a", "n"];
+
+        for id in (0..5).rev() {
+            if self.vars.get(id).get_level() >= self.bapdata.caps[id]
+                || !self.get_variable_conditions(id)
+            {
+                continue;
+            }
+
+            cost = self.vars.get(id).get_cost();
+
+            while self.rho > cost && self.get_variable_conditions(id) {
+                coast_eval = self.eval_coast(id, cost);
+                ratio_eval = self.eval_ratio(id);
+
+                if coast_eval != BuyEval::SKIP {
+                    if ratio_eval == BuyEval::SKIP {
+                        break;
+                    }
+                    if coast_eval == BuyEval::FORK {
+                        let mut fork: BaP = self.fork();
+                        let lvl: u32 = self.vars.get(id).get_level();
+                        fork.bapdata.caps[id] = lvl;
+                        let res: SimRes = fork.simulate();
+                        if res.t < self.best_res.t {
+                            self.best_res.t = res.t;
+                            self.best_res.var_buys = res.var_buys;
+                        }
+                    }
+
+                    self.rho = log10sub(self.rho, cost);
+                    self.vars.getm(id).buy();
+                    cost = self.vars.get(id).get_cost();
+                    if id == 4 {
+                        self.nterm = basel_term(self.vars.n.value as u32);
+                    }
+
+                    if self.maxrho > self.data.tau * 2.5 - 5. {
+                        self.varbuys.push(VarBuy {
+                            symb: s!(names[id]),
+                            lvl: self.vars.get(id).get_level(),
+                            t: self.t,
+                        })
+                    }
+                } else {
+                    self.bapdata.caps[id] = self.vars.get(id).get_level();
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exact_tail(n: u32) -> f64 {
+        std::f64::consts::PI.powi(2) / 6. - (1..=n).map(|k| 1. / (k as f64).powi(2)).sum::<f64>()
+    }
+
+    fn asymptotic_tail(n: f64) -> f64 {
+        1. / n - 1. / (2. * n.powi(2)) + 1. / (6. * n.powi(3)) - 1. / (30. * n.powi(5))
+    }
+
+    #[test]
+    fn basel_term_exact_meets_asymptotic() {
+        for n in [19, 20] {
+            assert!((exact_tail(n) / asymptotic_tail(n as f64) - 1.).abs() < 1e-9);
+        }
+        assert_eq!(basel_term(19), -exact_tail(19).log10());
+        assert_eq!(basel_term(20), -asymptotic_tail(20.).log10());
+        assert!((basel_term(20) - -exact_tail(20).log10()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn basel_term_grows_like_n() {
+        // the tail is about 1/n, so the term is about log10(n)
+        assert_eq!(
+            basel_term(1),
+            -(std::f64::consts::PI.powi(2) / 6. - 1.).log10()
+        );
+        assert!((basel_term(1_000_000) - 6.).abs() < 1e-6);
+    }
+}
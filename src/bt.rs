@@ -0,0 +1,373 @@
+use crate::s;
+use crate::utils::*;
+
+/// Milestone tree: tai and rao exponent.
+pub const MILESTONES: Milestones<2> = Milestones {
+    points: &[20., 40., 60., 80., 100., 120.],
+    max_levels: [3, 3],
+    order: [0, 1],
+};
+
+struct BTvars {
+    tai: Variable<FirstFreeCost<ExponentialCost>, StepwiseValue>,
+    rao: Variable<ExponentialCost, ExponentialValue>,
+}
+
+impl BTvars {
+    fn init() -> Self {
+        BTvars {
+            tai: Variable::new(
+                FirstFreeCost {
+                    model: ExponentialCost::new(15., 2.),
+                },
+                StepwiseValue::new(2., 10),
+            ),
+            rao: Variable::new(ExponentialCost::new(5., 10.), ExponentialValue::new(2.)),
+        }
+    }
+
+    fn getm(&mut self, id: usize) -> &mut dyn VariableTrait {
+        match id {
+            0 => &mut self.tai,
+            _ => &mut self.rao,
+        }
+    }
+
+    fn get(&self, id: usize) -> &dyn VariableTrait {
+        match id {
+            0 => &self.tai,
+            _ => &self.rao,
+        }
+    }
+
+    fn set(&mut self, lvls: [u32; 2]) {
+        for (i, level) in lvls.iter().enumerate() {
+            self.getm(i).set(*level);
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct BTdata {
+    caps: [u32; 2],
+    /// Milestone levels, in `MILESTONES` order.
+    pub milestones: [u32; 2],
+    /// Derive the milestones from rho and tau instead of keeping the given ones.
+    pub derive_milestones: bool,
+    pub do_coasting: bool,
+}
+
+pub struct BTstate {
+    pub levels: [u32; 2],
+}
+
+pub struct BT {
+    data: TheoryData,
+    pub btdata: BTdata,
+    pub goal: f64,
+    rho: f64,
+    maxrho: f64,
+    multiplier: f64,
+    vars: BTvars,
+    varbuys: Vec<VarBuy>,
+
+    t: f64,
+    dt: f64,
+    ddt: f64,
+    depth: u32,
+
+    best_res: SimRes,
+}
+
+impl BT {
+    pub fn new(data: TheoryData, goal: f64, state: Option<BTstate>) -> Self {
+        let mut bt: BT = BT {
+            data,
+            btdata: BTdata {
+                caps: [u32::MAX; 2],
+                milestones: MILESTONES.max_levels,
+                derive_milestones: true,
+                do_coasting: true,
+            },
+            goal,
+            rho: 0.,
+            maxrho: 0.,
+            multiplier: 0.,
+            vars: BTvars::init(),
+            varbuys: Vec::new(),
+
+            t: 0.,
+            dt: 1.5,
+            ddt: 1.0001,
+            depth: 0,
+
+            best_res: SimRes::default(),
+        };
+
+        if let Some(state) = state {
+            bt.vars.set(state.levels);
+        }
+
+        bt.rho = bt.data.rho;
+        bt.multiplier = bt.get_multiplier(bt.data.tau);
+
+        bt
+    }
+
+    pub fn fork(&self) -> Self {
+        let mut new: BT = BT {
+            data: self.data,
+            btdata: self.btdata,
+            goal: self.goal,
+            rho: self.rho,
+            maxrho: self.maxrho,
+            multiplier: self.multiplier,
+            vars: BTvars::init(),
+            varbuys: self.varbuys.clone(),
+            t: self.t,
+            dt: self.dt,
+            ddt: self.ddt,
+            depth: self.depth + 1,
+
+            best_res: SimRes::default(),
+        };
+
+        for i in 0..2 {
+            new.vars.getm(i).set(self.vars.get(i).get_level())
+        }
+
+        new
+    }
+
+    /// Milestones reached with the max of rho and the last pub's rho.
+    fn update_milestones(&mut self) {
+        if self.btdata.derive_milestones {
+            let rho = self.maxrho.max(self.data.tau * 2.5);
+            self.btdata.milestones = MILESTONES.at(rho);
+        }
+    }
+
+    fn get_multiplier(&self, tau: f64) -> f64 {
+        tau * 1.347 - 3.9
+    }
+
+    fn get_rhodot(&self) -> f64 {
+        self.vars.tai.value * (1. + 0.05 * self.btdata.milestones[0] as f64)
+            + self.vars.rao.value * (1. + 0.05 * self.btdata.milestones[1] as f64)
+            + self.multiplier
+    }
+
+    fn eval_coast_one(&self, dist: f64, lbound: f64, ubound: f64) -> BuyEval {
+        if dist > ubound {
+            BuyEval::BUY
+        } else if dist > lbound {
+            BuyEval::FORK
+        } else {
+            BuyEval::SKIP
+        }
+    }
+
+    fn eval_coast(&self, id: usize, cost: f64) -> BuyEval {
+        let dist: f64 = self.goal - cost;
+        if dist > 3. || !self.btdata.do_coasting {
+            return BuyEval::BUY;
+        }
+        match id {
+            0 => self.eval_coast_one(dist, 0.4, 1.2),
+            1 => self.eval_coast_one(dist, 0.2, 0.8),
+            _ => BuyEval::SKIP,
+        }
+    }
+
+    fn eval_ratio(&self, id: usize) -> BuyEval {
+        if match id {
+            0 => {
+                self.vars.tai.cost + (7. + (self.vars.tai.level % 10) as f64).log10()
+                    < self.vars.rao.cost
+            }
+            1 => true,
+            _ => false,
+        } {
+            BuyEval::BUY
+        } else {
+            BuyEval::SKIP
+        }
+    }
+
+    fn tick(&mut self) {
+        self.rho = log10add(self.rho, self.get_rhodot() + self.dt.log10());
+        self.maxrho = self.maxrho.max(self.rho);
+
+        self.t += self.dt / 1.5;
+        self.dt *= self.ddt;
+    }
+
+    pub fn simulate(&mut self) -> SimRes {
+        while self.maxrho < self.goal {
+            self.update_milestones();
+            self.tick();
+            self.buy();
+        }
+
+        if self.t < self.best_res.t {
+            SimRes {
+                t: self.t,
+                var_buys: Some(self.varbuys.clone()),
+            }
+        } else {
+            SimRes {
+                t: self.best_res.t,
+                var_buys: self.best_res.var_buys.clone(),
+            }
+        }
+    }
+
+    fn buy(&mut self) {
+        let mut cost: f64;
+        let mut coast_eval: BuyEval;
+        let mut ratio_eval: BuyEval;
+        let variables: [(usize, &str); 2] = [(1, "rao"), (0, "tai")];
+
+        for (id, name) in variables {
+            if self.vars.get(id).get_level() >= self.btdata.caps[id] {
+                continue;
+            }
+
+            cost = self.vars.get(id).get_cost();
+
+            while self.rho > cost {
+                coast_eval = self.eval_coast(id, cost);
+                ratio_eval = self.eval_ratio(id);
+
+                if coast_eval != BuyEval::SKIP {
+                    if ratio_eval == BuyEval::SKIP {
+                        break;
+                    }
+                    if coast_eval == BuyEval::FORK {
+                        let mut fork: BT = self.fork();
+                        let lvl: u32 = self.vars.get(id).get_level();
+                        fork.btdata.caps[id] = lvl;
+                        let res: SimRes = fork.simulate();
+                        if res.t < self.best_res.t {
+                            self.best_res.t = res.t;
+                            self.best_res.var_buys = res.var_buys;
+                        }
+                    }
+
+                    self.rho = log10sub(self.rho, cost);
+                    self.vars.getm(id).buy();
+                    cost = self.vars.get(id).get_cost();
+
+                    if self.maxrho > self.data.tau * 2.5 - 5. {
+                        self.varbuys.push(VarBuy {
+                            symb: s!(name),
+                            lvl: self.vars.get(id).get_level(),
+                            t: self.t,
+                        })
+                    }
+                } else {
+                    self.btdata.caps[id] = self.vars.get(id).get_level();
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// BT with the given levels and purchases disabled, so rho grows linearly.
+    fn frozen_bt(tau: f64, goal: f64, levels: [u32; 2], milestones: [u32; 2]) -> BT {
+        let mut bt = BT::new(
+            TheoryData {
+                tau,
+                students: 0,
+                rho: 0.,
+            },
+            goal,
+            Some(BTstate { levels }),
+        );
+        bt.btdata.caps = levels;
+        bt.btdata.milestones = milestones;
+        bt.btdata.derive_milestones = false;
+        bt
+    }
+
+    /// Sim time is dt / 1.5 per tick, so rho = rhodot * 1.5 * t.
+    fn assert_close(t: f64, expected: f64) {
+        assert!(
+            (t - expected).abs() / expected < 1e-3,
+            "t = {t}, expected {expected}"
+        );
+    }
+
+    #[test]
+    fn no_purchases_no_milestones() {
+        // tai 10 = 10, rao 3 = 8, multiplier at tau 30 is 10^36.51
+        // rho reaches 1e46 after 10^(46 - 36.51) / (80 * 1.5) s
+        let mut bt = frozen_bt(30., 46., [10, 3], [0, 0]);
+        assert_close(bt.simulate().t, 10f64.powf(46. - 36.51) / (80. * 1.5));
+    }
+
+    #[test]
+    fn no_purchases_max_milestones() {
+        // tai 20 = 30, rao 10 = 1024, both to the 1.15
+        // multiplier at tau 20 is 10^23.04
+        let rhodot: f64 = (30f64 * 1024.).powf(1.15) * 10f64.powf(23.04);
+        let mut bt = frozen_bt(20., 35., [20, 10], MILESTONES.max_levels);
+        assert_close(bt.simulate().t, 1e35 / (rhodot * 1.5));
+    }
+
+    fn fresh_bt(tau: f64, goal: f64, do_coasting: bool) -> BT {
+        let mut bt = BT::new(
+            TheoryData {
+                tau,
+                students: 0,
+                rho: 0.,
+            },
+            goal,
+            None,
+        );
+        bt.btdata.do_coasting = do_coasting;
+        bt
+    }
+
+    #[test]
+    fn buys_rao_always_and_tai_by_ratio() {
+        let mut bt = fresh_bt(20., 50., false);
+        bt.simulate();
+
+        // rao level n costs 5 * 10^n, every affordable level up to the goal is bought
+        assert_eq!(bt.vars.rao.level, 50);
+        assert!(bt.rho < bt.vars.rao.cost);
+        // tai stops at the first level failing the ratio against rao or out of reach
+        let tai = &bt.vars.tai;
+        assert!(tai.level > 0);
+        assert!(
+            tai.cost + (7. + (tai.level % 10) as f64).log10() >= bt.vars.rao.cost
+                || tai.cost > bt.rho
+        );
+    }
+
+    #[test]
+    fn coasting_beats_buying_to_the_end() {
+        let no_coast = fresh_bt(20., 55., false).simulate();
+        let coast = fresh_bt(20., 55., true).simulate();
+
+        assert!(coast.t < no_coast.t, "{} >= {}", coast.t, no_coast.t);
+        // the last purchases are made well before the goal
+        let last = coast.var_buys.unwrap().last().unwrap().t;
+        assert!(last < coast.t);
+    }
+
+    #[test]
+    fn milestones_from_last_pub_and_rho() {
+        let mut bt = fresh_bt(20., 65., false);
+        bt.simulate();
+
+        // 2 points from the last pub's rho of 50, a 3rd at 60
+        assert_eq!(bt.btdata.milestones, [3, 0]);
+    }
+}
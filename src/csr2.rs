@@ -1,6 +1,52 @@
+use std::str::FromStr;
+
 use crate::s;
 use crate::utils::*;
 
+/// Milestone tree: q1 exponent and c2 error term.
+pub const MILESTONES: Milestones<2> = Milestones {
+    points: &[10., 45., 80., 115.],
+    max_levels: [3, 1],
+    order: [0, 1],
+};
+
+/// Increment of the swap distance between two swap forks, in log10.
+const SWAP_STEP: f64 = 0.5;
+
+/// Purchase strategies for CSR2.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CSR2strat {
+    /// Ratios against q2, n and c2, with coasting.
+    Active,
+    /// Coasting only, every variable is bought as soon as it is affordable.
+    Idle,
+    /// Active, with the q1 exponent points moved to the c2 error term near the end.
+    Swap,
+}
+
+impl CSR2strat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CSR2strat::Active => "active",
+            CSR2strat::Idle => "idle",
+            CSR2strat::Swap => "swap",
+        }
+    }
+}
+
+impl FromStr for CSR2strat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &s.to_lowercase()[..] {
+            "active" => Ok(CSR2strat::Active),
+            "idle" => Ok(CSR2strat::Idle),
+            "swap" => Ok(CSR2strat::Swap),
+            _ => Err(format!("Unknown CSR2 strategy: {s}")),
+        }
+    }
+}
+
 struct CSR2vars {
     q1: Variable<FirstFreeCost<ExponentialCost>, StepwiseValue>,
     q2: Variable<ExponentialCost, ExponentialValue>,
@@ -62,6 +108,15 @@ impl CSR2vars {
 pub struct CSR2data {
     caps: [u32; 5],
     pub do_coasting: bool,
+    pub strat: CSR2strat,
+    /// Milestone levels, [q1 exponent, c2 error term]. The swap strategy reallocates their sum.
+    pub milestones: [u32; 2],
+    /// Derive the milestones from rho and tau instead of keeping the given ones.
+    pub derive_milestones: bool,
+    /// Distance to the goal (log10) under which the swap strategy searches swaps.
+    pub swap_window: f64,
+    next_swap_check: f64,
+    swapped: bool,
 }
 
 impl Copy for CSR2data {}
@@ -97,6 +152,12 @@ impl CSR2 {
             csr2data: CSR2data {
                 caps: [u32::MAX; 5],
                 do_coasting: true,
+                strat: CSR2strat::Active,
+                milestones: MILESTONES.max_levels,
+                derive_milestones: true,
+                swap_window: 2.,
+                next_swap_check: 2.,
+                swapped: false,
             },
             goal: goal,
             rho: 0.,
@@ -158,6 +219,60 @@ impl CSR2 {
         tau * 0.55075 - 200f64.log10()
     }
 
+    /// Milestones reached with the max of rho and the last pub's rho,
+    /// spent on the c2 error term first once swapped.
+    fn update_milestones(&mut self) {
+        if self.csr2data.derive_milestones {
+            let rho = self.maxrho.max(self.data.tau * 2.5);
+            let order: [usize; 2] = if self.csr2data.swapped {
+                [1, 0]
+            } else {
+                MILESTONES.order
+            };
+            self.csr2data.milestones = MILESTONES.allocate(MILESTONES.points_at(rho), &order);
+        }
+    }
+
+    /// Moves points from the q1 exponent to the c2 error term.
+    fn swap(&mut self) {
+        let points: u32 = self.csr2data.milestones.iter().sum();
+        self.csr2data.milestones = MILESTONES.allocate(points, &[1, 0]);
+        self.csr2data.swapped = true;
+
+        if self.maxrho > self.data.tau * 2.5 - 3. {
+            self.varbuys.push(VarBuy {
+                symb: s!("swap"),
+                lvl: self.csr2data.milestones[0],
+                t: self.t,
+            })
+        }
+    }
+
+    /// The error term only feeds q, so it pays off late. Near the goal, a fork swaps at each step.
+    fn check_swap(&mut self) {
+        if self.csr2data.strat != CSR2strat::Swap || self.csr2data.swapped {
+            return;
+        }
+        // with enough points for both, or none in the q1 exponent yet, there is nothing to move
+        let points: u32 = self.csr2data.milestones.iter().sum();
+        if points >= MILESTONES.total() || self.csr2data.milestones[0] == 0 {
+            return;
+        }
+        let dist: f64 = self.goal - self.maxrho;
+        if dist > self.csr2data.next_swap_check.min(self.csr2data.swap_window) {
+            return;
+        }
+        self.csr2data.next_swap_check = dist - SWAP_STEP;
+
+        let mut fork: CSR2 = self.fork();
+        fork.swap();
+        let res: SimRes = fork.simulate();
+        if res.t < self.best_res.t {
+            self.best_res.t = res.t;
+            self.best_res.var_buys = res.var_buys;
+        }
+    }
+
     fn eval_coast_one(&self, dist: f64, lbound: f64, ubound: f64) -> BuyEval {
         if dist > ubound {
             BuyEval::BUY
@@ -184,6 +299,9 @@ impl CSR2 {
     }
 
     fn eval_ratio(&self, id: usize) -> BuyEval {
+        if self.csr2data.strat == CSR2strat::Idle {
+            return BuyEval::BUY;
+        }
         if match id {
             0 => {
                 self.vars.q1.cost + (7. + (self.vars.q1.level % 10) as f64).log10()
@@ -221,16 +339,20 @@ impl CSR2 {
     fn tick(&mut self) {
         let bonus: f64 = self.multiplier + self.dt.log10();
 
+        let ms = self.csr2data.milestones;
+        let error_n: f64 = if ms[1] >= 1 {
+            self.vars.n.value + self.vars.c2.level as f64
+        } else {
+            self.vars.n.value
+        };
+
         self.q = log10add(
             self.q,
-            self.vars.c1.value
-                + 2. * self.vars.c2.value
-                + self.get_error(self.vars.n.value + self.vars.c2.level as f64)
-                + bonus,
+            self.vars.c1.value + 2. * self.vars.c2.value + self.get_error(error_n) + bonus,
         );
         self.rho = log10add(
             self.rho,
-            self.vars.q1.value * 1.15 + self.vars.q2.value + self.q + bonus,
+            self.vars.q1.value * (1. + 0.05 * ms[0] as f64) + self.vars.q2.value + self.q + bonus,
         );
         self.maxrho = self.maxrho.max(self.rho);
 
@@ -240,8 +362,10 @@ impl CSR2 {
 
     pub fn simulate(&mut self) -> SimRes {
         while self.maxrho < self.goal {
+            self.update_milestones();
             self.tick();
             self.buy();
+            self.check_swap();
         }
 
         if self.t < self.best_res.t {
@@ -1,8 +1,46 @@
 use core::f64;
+use std::str::FromStr;
 
 use crate::s;
 use crate::utils::*;
 
+/// Milestone tree: n exponent and a2.
+pub const MILESTONES: Milestones<2> = Milestones {
+    points: &[25., 50.],
+    max_levels: [1, 1],
+    order: [0, 1],
+};
+
+/// Purchase strategies for DE.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DEstrat {
+    /// a0 only in the first minute, a2 never bought.
+    Base,
+    /// Also buys a0 and a2 while they pull their weight.
+    A2A0,
+}
+
+impl DEstrat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DEstrat::Base => "base",
+            DEstrat::A2A0 => "a2a0",
+        }
+    }
+}
+
+impl FromStr for DEstrat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &s.to_lowercase()[..] {
+            "base" => Ok(DEstrat::Base),
+            "a2a0" => Ok(DEstrat::A2A0),
+            _ => Err(format!("Unknown DE strategy: {s}")),
+        }
+    }
+}
+
 #[derive(Debug)]
 struct MaxXValue {
     power: f64,
@@ -114,7 +152,13 @@ impl DEvars {
 
 #[derive(Clone, Debug)]
 pub struct DEdata {
+    caps: [u32; 6],
     pub do_coasting: bool,
+    pub strat: DEstrat,
+    /// Milestone levels, [n exponent, a2].
+    pub milestones: [u32; 2],
+    /// Derive the milestones from rho and tau instead of keeping the given ones.
+    pub derive_milestones: bool,
 }
 
 impl Copy for DEdata {}
@@ -143,13 +187,26 @@ pub struct DE {
     t: f64,
     dt: f64,
     ddt: f64,
+    depth: u32,
+    /// The sim without coasting from this branch's start has been run, or this branch is
+    /// a coasting fork. A sim coasting from its start keeps the best of the two, as a
+    /// skipped purchase caps a variable for the rest of the branch.
+    no_coast_kept: bool,
+
+    best_res: SimRes,
 }
 
 impl DE {
     pub fn new(data: TheoryData, goal: f64, state: Option<DEstate>) -> Self {
         let mut de = DE {
             data: data,
-            dedata: DEdata { do_coasting: true },
+            dedata: DEdata {
+                caps: [u32::MAX; 6],
+                do_coasting: true,
+                strat: DEstrat::Base,
+                milestones: [0; 2],
+                derive_milestones: true,
+            },
             goal: goal,
 
             rho: 0.,
@@ -164,6 +221,10 @@ impl DE {
             t: 0.,
             dt: 1.5,
             ddt: 1.00001,
+            depth: 0,
+            no_coast_kept: false,
+
+            best_res: SimRes::default(),
         };
 
         match state {
@@ -198,6 +259,10 @@ impl DE {
             t: self.t,
             dt: self.dt,
             ddt: self.ddt,
+            depth: self.depth + 1,
+            no_coast_kept: self.no_coast_kept,
+
+            best_res: SimRes::default(),
         };
 
         for i in 0..6 {
@@ -211,18 +276,55 @@ impl DE {
         tau * 0.4 - 4f64.log10()
     }
 
+    fn eval_coast_one(&self, dist: f64, lbound: f64, ubound: f64) -> BuyEval {
+        if dist > ubound {
+            BuyEval::BUY
+        } else if dist > lbound {
+            BuyEval::FORK
+        } else {
+            BuyEval::SKIP
+        }
+    }
+
+    fn eval_coast(&self, id: usize, cost: f64) -> BuyEval {
+        let dist: f64 = self.goal - cost;
+        if dist > 3. || !self.dedata.do_coasting {
+            return BuyEval::BUY;
+        }
+        match id {
+            0 => self.eval_coast_one(dist, 0.2, 0.8),
+            3 => self.eval_coast_one(dist, 0.3, 1.2),
+            5 => self.eval_coast_one(dist, 0., 1.),
+            _ => BuyEval::BUY,
+        }
+    }
+
     fn eval_ratio(&self, id: usize) -> BuyEval {
         let mut next_coast = self.vars.max_x.cost;
         if self.dedata.do_coasting {
             next_coast = next_coast.min(self.goal)
         };
 
+        let extra: bool = self.dedata.strat == DEstrat::A2A0;
+
         if match id {
             0 => self.vars.n.cost + 5f64.log10() < next_coast,
             1 => self.vars.m.cost + 10f64.log10() < next_coast && self.maxrho * 0.4 < self.data.tau,
-            2 => self.t < 60.,
+            2 => {
+                self.t < 60.
+                    // in the x rate a0 only counts through a log, so it is bought for the
+                    // rho rate, once it is within 10x of 2 a1 x
+                    || extra
+                        && self.vars.a0.cost + 5f64.log10() < next_coast
+                        && self.vars.a0.value > 2f64.log10() + self.vars.a1.value + self.x - 1.
+            }
             3 => self.vars.a1.cost + ((5 + self.vars.a1.level % 7) as f64).log10() < next_coast,
-            4 => false,
+            4 => {
+                extra
+                    && self.vars.a2.cost + 5f64.log10() < next_coast
+                    // a2 is worth nothing before its first level
+                    && (self.vars.a2.level == 0 || self.vars.a2.value > self.vars.a1.value - 1.)
+            }
             5 => true,
             _ => false,
         } {
@@ -232,10 +334,34 @@ impl DE {
         }
     }
 
+    fn get_variable_conditions(&self, id: usize) -> bool {
+        match id {
+            4 => self.dedata.milestones[1] >= 1,
+            _ => true,
+        }
+    }
+
+    /// Milestones reached with the max of rho and the last pub's rho.
+    fn update_milestones(&mut self) {
+        if self.dedata.derive_milestones {
+            self.dedata.milestones = MILESTONES.at(self.maxrho.max(self.data.tau * 2.5));
+        }
+    }
+
+    /// Exponent of n in the x rate, lowered by the n exponent milestone.
+    fn get_vn(&self) -> f64 {
+        self.vars.n.value * (1.2 - 0.6 * self.dedata.milestones[0] as f64)
+    }
+
     fn tick(&mut self) {
         let logdt = self.dt.log10();
-        let vn = self.vars.n.value * (1.2 - 0.6 * 0.);
+        let vn = self.get_vn();
         let va0 = self.vars.a0.value * 3.;
+        let va12 = if self.get_variable_conditions(4) {
+            log10add(self.vars.a1.value, self.vars.a2.value)
+        } else {
+            self.vars.a1.value
+        };
 
         self.tvar = log10add(self.tvar, self.multiplier + logdt);
         self.x = log10add(
@@ -247,7 +373,7 @@ impl DE {
         self.x = self.x.min(self.vars.max_x.value);
         self.q = log10add(
             self.q,
-            self.vars.a1.value + self.x + self.vars.m.value - self.tvar + logdt,
+            va12 + self.x + self.vars.m.value - self.tvar + logdt,
         );
 
         let rhodot = log10add(
@@ -264,7 +390,15 @@ impl DE {
     }
 
     pub fn simulate(&mut self) -> SimRes {
+        if self.dedata.do_coasting && !self.no_coast_kept {
+            self.no_coast_kept = true;
+            let mut fork: DE = self.fork();
+            fork.dedata.do_coasting = false;
+            self.best_res = fork.simulate();
+        }
+
         while self.maxrho < self.goal {
+            self.update_milestones();
             self.tick();
             //println!("{:?}", self);
             //println!("{} {} {} {}", self.rho, self.maxrho, self.x, self.q);
@@ -273,24 +407,52 @@ impl DE {
         //
         //println!("{}", get_time_string(self.t));
 
-        SimRes {
-            t: self.t,
-            var_buys: Some(self.varbuys.clone()),
+        if self.t < self.best_res.t {
+            SimRes {
+                t: self.t,
+                var_buys: Some(self.varbuys.clone()),
+            }
+        } else {
+            SimRes {
+                t: self.best_res.t,
+                var_buys: self.best_res.var_buys.clone(),
+            }
         }
     }
 
     fn buy(&mut self) {
         let mut cost: f64;
+        let mut coast_eval: BuyEval;
         let mut ratio_eval: BuyEval;
         let names = ["n", "m", "a0", "a1", "a2", "max x"];
 
         for i in (0..6).rev() {
+            if self.vars.get(i).get_level() >= self.dedata.caps[i]
+                || !self.get_variable_conditions(i)
+            {
+                continue;
+            }
             cost = self.vars.get(i).get_cost();
             while self.rho > cost {
+                coast_eval = self.eval_coast(i, cost);
                 ratio_eval = self.eval_ratio(i);
+                if coast_eval == BuyEval::SKIP {
+                    self.dedata.caps[i] = self.vars.get(i).get_level();
+                    break;
+                }
                 if ratio_eval == BuyEval::SKIP {
                     break;
                 }
+                if coast_eval == BuyEval::FORK {
+                    let mut fork: DE = self.fork();
+                    fork.dedata.caps[i] = self.vars.get(i).get_level();
+                    fork.no_coast_kept = true;
+                    let res: SimRes = fork.simulate();
+                    if res.t < self.best_res.t {
+                        self.best_res.t = res.t;
+                        self.best_res.var_buys = res.var_buys;
+                    }
+                }
 
                 self.rho = log10sub(self.rho, cost);
                 self.vars.getm(i).buy();
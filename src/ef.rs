@@ -1,6 +1,105 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
 use crate::s;
 use crate::utils::*;
 
+/// Milestone tree: R and I dimensions, a1/a2/a3 unlocks, a exponent, b2 base and c2 base.
+pub const MILESTONES: Milestones<5> = Milestones {
+    points: &[
+        10., 20., 30., 40., 50., 70., 90., 110., 130., 150., 250., 275., 300., 325.,
+    ],
+    max_levels: [2, 3, 5, 2, 2],
+    order: [0, 1, 2, 3, 4],
+};
+
+/// Increment of the swap distance between two swap forks, in log10.
+const SWAP_STEP: f64 = 0.5;
+
+/// Purchase strategy presets for EF, see `EFthresholds`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EFstrat {
+    /// Ratios against a2/a3 and coasting before the goal and each milestone.
+    Active,
+    /// Buys everything as soon as it is affordable.
+    Idle,
+    /// Active, but buys everything without coasting until the last pub's rho is recovered.
+    Recovery,
+}
+
+impl EFstrat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EFstrat::Active => "active",
+            EFstrat::Idle => "idle",
+            EFstrat::Recovery => "recovery",
+        }
+    }
+
+    pub fn thresholds(&self) -> EFthresholds {
+        let active = EFthresholds {
+            bc_ratio: Some(5f64.log10()),
+            bc_ratio_ms: 2,
+            stepwise_ratios: true,
+            coast_dist: 3.,
+            coast_q1: (0.6, 1.8),
+            coast_q2: (0.2, 1.5),
+            coast_a1: (0.3, 1.5),
+            recovery_bc: true,
+            recovery_all: false,
+        };
+        match self {
+            EFstrat::Active => active,
+            EFstrat::Idle => EFthresholds {
+                bc_ratio: None,
+                stepwise_ratios: false,
+                coast_dist: 0.,
+                ..active
+            },
+            EFstrat::Recovery => EFthresholds {
+                recovery_all: true,
+                ..active
+            },
+        }
+    }
+}
+
+impl FromStr for EFstrat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &s.to_lowercase()[..] {
+            "active" => Ok(EFstrat::Active),
+            "idle" => Ok(EFstrat::Idle),
+            "recovery" => Ok(EFstrat::Recovery),
+            _ => Err(format!("Unknown EF strategy: {s}")),
+        }
+    }
+}
+
+/// Purchase thresholds used by `eval_ratio` and `eval_coast`. Distances are in log10.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EFthresholds {
+    /// b and c are bought when this much cheaper than a2 (b) or a3 (c), or whenever
+    /// affordable if `None`.
+    pub bc_ratio: Option<f64>,
+    /// a milestone level under which b and c are always bought.
+    pub bc_ratio_ms: u32,
+    /// Buy q1 and a1 only when cheap enough relative to q2, given their level mod 10.
+    pub stepwise_ratios: bool,
+    /// Distance to the goal or next milestone above which everything is bought.
+    pub coast_dist: f64,
+    /// Coasting windows of q1, q2 and a1: skip under the first bound, fork up to the second.
+    pub coast_q1: (f64, f64),
+    pub coast_q2: (f64, f64),
+    pub coast_a1: (f64, f64),
+    /// Always buy b and c before the last pub's rho is recovered.
+    pub recovery_bc: bool,
+    /// Buy everything without coasting before the last pub's rho is recovered.
+    pub recovery_all: bool,
+}
+
 #[derive(Debug)]
 struct EFvars {
     tdot: Variable<ExponentialCost, LinearValue>,
@@ -110,6 +209,15 @@ impl EFvars {
 pub struct EFdata {
     caps: [u32; 10],
     pub do_coasting: bool,
+    pub thresholds: EFthresholds,
+    /// Explore swap timings over the pub instead of swapping at `swap_dist`.
+    pub do_swap_search: bool,
+    /// Distance to the goal (log10) at which points move from b2/c2 to the a exponent.
+    pub swap_dist: f64,
+    /// Distance to the goal (log10) under which swaps are searched.
+    pub swap_window: f64,
+    next_swap_check: f64,
+    swapped: bool,
 }
 
 impl Copy for EFdata {}
@@ -136,7 +244,7 @@ pub struct EF {
     q: f64,
     vars: EFvars,
     varbuys: Vec<VarBuy>,
-    milestones: [usize; 5],
+    milestones: [u32; 5],
     next_milestone_cost: f64,
 
     t: f64,
@@ -154,6 +262,12 @@ impl EF {
             efdata: EFdata {
                 caps: [u32::MAX; 10],
                 do_coasting: true,
+                thresholds: EFstrat::Active.thresholds(),
+                do_swap_search: false,
+                swap_dist: f64::MAX,
+                swap_window: 4.,
+                next_swap_check: f64::MAX,
+                swapped: false,
             },
             goal: goal,
 
@@ -234,46 +348,77 @@ impl EF {
         tau * 0.09675
     }
 
+    /// Before the swap, points go to the b2 and c2 bases before the a exponent.
     fn update_milestones(&mut self) {
-        const POINTS: [f64; 14] = [
-            10., 20., 30., 40., 50., 70., 90., 110., 130., 150., 250., 275., 300., 325.,
-        ];
-        const MAX_MS: [usize; 5] = [2, 3, 5, 2, 2];
         let rho = self.maxrho.max(self.data.tau * (1. / 1.6));
         let ms_b2 = self.milestones[3];
         let ms_c2 = self.milestones[4];
-        self.milestones = [0; 5];
-        let mut stage: u32 = 0;
-
-        for point in &POINTS {
-            if rho >= *point {
-                stage += 1;
-            } else {
-                self.next_milestone_cost = *point;
-                break;
-            }
-        }
-        if stage == 14 {
-            self.next_milestone_cost = f64::MAX
-        }
+        self.next_milestone_cost = MILESTONES.next_cost(rho);
 
-        for i in 0..5 {
-            while self.milestones[i] < MAX_MS[i] && stage > 0 {
-                self.milestones[i] += 1;
-                stage -= 1;
-            }
-        }
+        let order: [usize; 5] = if self.efdata.swapped {
+            MILESTONES.order
+        } else {
+            [0, 1, 3, 4, 2]
+        };
+        self.milestones = MILESTONES.allocate(MILESTONES.points_at(rho), &order);
 
-        if self.milestones[3] > ms_b2 {
+        if self.milestones[3] != ms_b2 {
             self.vars
                 .update_b2_base(1.1 + 0.01 * self.milestones[3] as f64);
         }
-        if self.milestones[4] > ms_c2 {
+        if self.milestones[4] != ms_c2 {
             self.vars
                 .update_c2_base(1.1 + 0.0125 * self.milestones[4] as f64);
         }
     }
 
+    fn swap(&mut self) {
+        self.efdata.swapped = true;
+        self.update_milestones();
+
+        // logged even during recovery, as it is part of the schedule;
+        // a swap at the start is just the initial allocation
+        if self.t > 0. {
+            self.varbuys.push(VarBuy {
+                symb: s!("swap"),
+                lvl: self.milestones[2],
+                t: self.t,
+            })
+        }
+    }
+
+    /// The b2 and c2 bases compound in re and im, while the a exponent pays off at once,
+    /// so points are moved to a during the pub. Within `swap_window`, a fork swaps at each step.
+    fn check_swap(&mut self) {
+        if self.efdata.swapped {
+            return;
+        }
+        // with every milestone reached, both allocations are the same
+        if self.next_milestone_cost == f64::MAX {
+            self.efdata.swapped = true;
+            return;
+        }
+        let dist: f64 = self.goal - self.maxrho;
+        if !self.efdata.do_swap_search {
+            if dist < self.efdata.swap_dist {
+                self.swap();
+            }
+            return;
+        }
+        if dist > self.efdata.next_swap_check.min(self.efdata.swap_window) {
+            return;
+        }
+        self.efdata.next_swap_check = dist - SWAP_STEP;
+
+        let mut fork: EF = self.fork();
+        fork.swap();
+        let res: SimRes = fork.simulate();
+        if res.t < self.best_res.t {
+            self.best_res.t = res.t;
+            self.best_res.var_buys = res.var_buys;
+        }
+    }
+
     fn eval_coast_one(&self, dist: f64, lbound: f64, ubound: f64) -> BuyEval {
         if dist > ubound {
             BuyEval::BUY
@@ -284,55 +429,56 @@ impl EF {
         }
     }
 
+    fn is_recovering(&self) -> bool {
+        self.maxrho < self.data.tau * (1. / 1.6)
+    }
+
     fn eval_coast(&self, id: usize, cost: f64) -> BuyEval {
         //return if id >= 8 || self.maxrho + 5f64.log10() < self.next_milestone_cost { BuyEval::BUY } else { BuyEval::SKIP };
+        let th = &self.efdata.thresholds;
         let dist: f64 = self.goal.min(self.next_milestone_cost) - cost;
-        if dist > 3. || !self.efdata.do_coasting {
+        if dist > th.coast_dist
+            || !self.efdata.do_coasting
+            || (th.recovery_all && self.is_recovering())
+        {
             return BuyEval::BUY;
         }
         match id {
-            1 => self.eval_coast_one(dist, 0.6, 1.8),
-            2 => self.eval_coast_one(dist, 0.2, 1.5),
-            7 => self.eval_coast_one(dist, 0.3, 1.5),
+            1 => self.eval_coast_one(dist, th.coast_q1.0, th.coast_q1.1),
+            2 => self.eval_coast_one(dist, th.coast_q2.0, th.coast_q2.1),
+            7 => self.eval_coast_one(dist, th.coast_a1.0, th.coast_a1.1),
             _ => BuyEval::BUY,
         }
     }
 
     fn eval_ratio(&self, id: usize) -> BuyEval {
         //return BuyEval::BUY;
-        let log10_5: f64 = 5f64.log10();
-        let recovery: bool = self.maxrho < self.data.tau * (1. / 1.6);
+        let th = &self.efdata.thresholds;
+        let recovery: bool = self.is_recovering();
+        if th.recovery_all && recovery {
+            return BuyEval::BUY;
+        }
+        let free_bc: bool = self.milestones[1] < th.bc_ratio_ms || (th.recovery_bc && recovery);
+        let buy_bc = |cost: f64, a_cost: f64| {
+            free_bc || th.bc_ratio.is_none_or(|ratio| cost + ratio < a_cost)
+        };
 
         if match id {
             0 => true,
             1 => {
-                self.vars.q1.cost + (10. + (self.vars.q1.level % 10) as f64).log10()
-                    < self.vars.q2.cost
+                !th.stepwise_ratios
+                    || self.vars.q1.cost + (10. + (self.vars.q1.level % 10) as f64).log10()
+                        < self.vars.q2.cost
             }
             2 => true,
-            3 => {
-                self.vars.b1.cost + log10_5 < self.vars.a2.cost
-                    || self.milestones[1] < 2
-                    || recovery
-            }
-            4 => {
-                self.vars.b2.cost + log10_5 < self.vars.a2.cost
-                    || self.milestones[1] < 2
-                    || recovery
-            }
-            5 => {
-                self.vars.c1.cost + log10_5 < self.vars.a3.cost
-                    || self.milestones[1] < 2
-                    || recovery
-            }
-            6 => {
-                self.vars.c2.cost + log10_5 < self.vars.a3.cost
-                    || self.milestones[1] < 2
-                    || recovery
-            }
+            3 => buy_bc(self.vars.b1.cost, self.vars.a2.cost),
+            4 => buy_bc(self.vars.b2.cost, self.vars.a2.cost),
+            5 => buy_bc(self.vars.c1.cost, self.vars.a3.cost),
+            6 => buy_bc(self.vars.c2.cost, self.vars.a3.cost),
             7 => {
-                self.vars.a1.cost + (4. + (self.vars.a1.level % 10) as f64 / 2.).log10()
-                    < self.vars.q2.cost
+                !th.stepwise_ratios
+                    || self.vars.a1.cost + (4. + (self.vars.a1.level % 10) as f64 / 2.).log10()
+                        < self.vars.q2.cost
                     || self.efdata.caps[2] <= self.vars.q2.level
             }
             8 | 9 => true,
@@ -426,6 +572,7 @@ impl EF {
         let mut prev_next_ms_cost: f64;
 
         while self.maxrho < self.goal {
+            self.check_swap();
             self.tick();
             prev_next_ms_cost = self.next_milestone_cost;
             if self.next_milestone_cost < 375. {
@@ -541,3 +688,17 @@ impl EF {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thresholds_round_trip_through_json() {
+        for strat in [EFstrat::Active, EFstrat::Idle, EFstrat::Recovery] {
+            let value = serde_json::to_value(strat.thresholds()).unwrap();
+            let read: EFthresholds = serde_json::from_value(value).unwrap();
+            assert_eq!(read, strat.thresholds(), "{}", strat.name());
+        }
+    }
+}
@@ -0,0 +1,426 @@
+use crate::s;
+use crate::utils::*;
+
+/// Milestone tree: lambda and q1 exponent.
+pub const MILESTONES: Milestones<2> = Milestones {
+    points: &[50., 100., 150., 200., 275., 350.],
+    max_levels: [3, 3],
+    order: [1, 0],
+};
+
+/// Increment of the swap distance between two swap forks, in log10.
+const SWAP_STEP: f64 = 0.5;
+
+/// log10 of the gamma function, Lanczos approximation.
+fn log10_gamma(x: f64) -> f64 {
+    const G: f64 = 7.;
+    const COEFS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+    let x = x - 1.;
+    let mut a = COEFS[0];
+    let t = x + G + 0.5;
+    for (i, coef) in COEFS.iter().enumerate().skip(1) {
+        a += coef / (x + i as f64);
+    }
+    ((2. * std::f64::consts::PI).sqrt() * a).log10() + (x + 0.5) * t.log10()
+        - t * std::f64::consts::E.log10()
+}
+
+struct FIvars {
+    q1: Variable<FirstFreeCost<ExponentialCost>, StepwiseValue>,
+    q2: Variable<ExponentialCost, ExponentialValue>,
+    k: Variable<ExponentialCost, StepwiseValue>,
+}
+
+impl FIvars {
+    fn init() -> Self {
+        FIvars {
+            q1: Variable::new(
+                FirstFreeCost {
+                    model: ExponentialCost::new(10., 2.),
+                },
+                StepwiseValue::new(2., 10),
+            ),
+            q2: Variable::new(ExponentialCost::new(1e3, 8.), ExponentialValue::new(2.)),
+            k: Variable::new(
+                ExponentialCost::new(100., 4.),
+                StepwiseValue::new_offset(2., 10, 1.),
+            ),
+        }
+    }
+
+    fn getm(&mut self, id: usize) -> &mut dyn VariableTrait {
+        match id {
+            0 => &mut self.q1,
+            1 => &mut self.q2,
+            _ => &mut self.k,
+        }
+    }
+
+    fn get(&self, id: usize) -> &dyn VariableTrait {
+        match id {
+            0 => &self.q1,
+            1 => &self.q2,
+            _ => &self.k,
+        }
+    }
+
+    fn set(&mut self, lvls: [u32; 3]) {
+        for (i, level) in lvls.iter().enumerate() {
+            self.getm(i).set(*level);
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct FIdata {
+    caps: [u32; 3],
+    pub do_coasting: bool,
+    /// Milestone points available, derived from tau unless overridden.
+    pub points: u32,
+    /// Explore swap timings near the goal instead of swapping at `swap_dist`.
+    pub do_swap_search: bool,
+    /// Distance to the goal (log10) at which points move from q1 to lambda.
+    pub swap_dist: f64,
+    /// Distance to the goal (log10) under which swaps are searched.
+    pub swap_window: f64,
+    next_swap_check: f64,
+    swapped: bool,
+}
+
+pub struct FIstate {
+    pub levels: [u32; 3],
+    pub q: f64,
+}
+
+pub struct FI {
+    data: TheoryData,
+    pub fidata: FIdata,
+    pub goal: f64,
+    rho: f64,
+    maxrho: f64,
+    multiplier: f64,
+    q: f64,
+    r: f64,
+    /// Current allocation, [lambda, q1 exponent].
+    milestones: [u32; 2],
+    vars: FIvars,
+    varbuys: Vec<VarBuy>,
+
+    t: f64,
+    dt: f64,
+    ddt: f64,
+    depth: u32,
+
+    best_res: SimRes,
+}
+
+impl FI {
+    pub fn new(data: TheoryData, goal: f64, state: Option<FIstate>) -> Self {
+        let mut fi: FI = FI {
+            data,
+            fidata: FIdata {
+                caps: [u32::MAX; 3],
+                do_coasting: true,
+                points: 0,
+                do_swap_search: true,
+                swap_dist: 2.,
+                swap_window: 4.,
+                next_swap_check: 4.,
+                swapped: false,
+            },
+            goal,
+            rho: 0.,
+            maxrho: 0.,
+            multiplier: 0.,
+            q: 0.,
+            r: 0.,
+            milestones: [0; 2],
+            vars: FIvars::init(),
+            varbuys: Vec::new(),
+
+            t: 0.,
+            dt: 1.5,
+            ddt: 1.0001,
+            depth: 0,
+
+            best_res: SimRes::default(),
+        };
+
+        if let Some(state) = state {
+            fi.vars.set(state.levels);
+            fi.q = state.q;
+        }
+
+        fi.rho = fi.data.rho;
+        fi.multiplier = fi.get_multiplier(fi.data.tau);
+        fi.fidata.points = MILESTONES.points_at(fi.data.tau * 2.5);
+        fi.update_milestones();
+
+        fi
+    }
+
+    pub fn fork(&self) -> Self {
+        let mut new: FI = FI {
+            data: self.data,
+            fidata: self.fidata,
+            goal: self.goal,
+            rho: self.rho,
+            maxrho: self.maxrho,
+            multiplier: self.multiplier,
+            q: self.q,
+            r: self.r,
+            milestones: self.milestones,
+            vars: FIvars::init(),
+            varbuys: self.varbuys.clone(),
+            t: self.t,
+            dt: self.dt,
+            ddt: self.ddt,
+            depth: self.depth + 1,
+
+            best_res: SimRes::default(),
+        };
+
+        for i in 0..3 {
+            new.vars.getm(i).set(self.vars.get(i).get_level())
+        }
+
+        new
+    }
+
+    fn get_multiplier(&self, tau: f64) -> f64 {
+        tau * 1.25 - 8.
+    }
+
+    /// Points go to the q1 exponent first before the swap, to lambda first after it.
+    fn update_milestones(&mut self) {
+        let order: [usize; 2] = if self.fidata.swapped { [0, 1] } else { MILESTONES.order };
+        self.milestones = MILESTONES.allocate(self.fidata.points, &order);
+    }
+
+    /// Order of the fractional integral.
+    fn get_lambda(&self) -> f64 {
+        1. - 1. / 2f64.powi(self.milestones[0] as i32)
+    }
+
+    fn swap(&mut self) {
+        self.fidata.swapped = true;
+        self.update_milestones();
+
+        if self.maxrho > self.data.tau * 2.5 - 5. {
+            self.varbuys.push(VarBuy {
+                symb: s!("swap"),
+                lvl: self.milestones[0],
+                t: self.t,
+            })
+        }
+    }
+
+    /// Swapping late keeps the q1 exponent during the part of the pub where
+    /// t^lambda is still small. Near the goal, a fork swaps at each step.
+    fn check_swap(&mut self) {
+        if self.fidata.swapped {
+            return;
+        }
+        // with every milestone maxed, both allocations are the same
+        if self.fidata.points >= MILESTONES.total() {
+            self.fidata.swapped = true;
+            return;
+        }
+        let dist: f64 = self.goal - self.maxrho;
+        if !self.fidata.do_swap_search {
+            if dist < self.fidata.swap_dist {
+                self.swap();
+            }
+            return;
+        }
+        if dist > self.fidata.next_swap_check.min(self.fidata.swap_window) {
+            return;
+        }
+        self.fidata.next_swap_check = dist - SWAP_STEP;
+
+        let mut fork: FI = self.fork();
+        fork.swap();
+        let res: SimRes = fork.simulate();
+        if res.t < self.best_res.t {
+            self.best_res.t = res.t;
+            self.best_res.var_buys = res.var_buys;
+        }
+    }
+
+    fn eval_coast_one(&self, dist: f64, lbound: f64, ubound: f64) -> BuyEval {
+        if dist > ubound {
+            BuyEval::BUY
+        } else if dist > lbound {
+            BuyEval::FORK
+        } else {
+            BuyEval::SKIP
+        }
+    }
+
+    fn eval_coast(&self, id: usize, cost: f64) -> BuyEval {
+        let dist: f64 = self.goal - cost;
+        if dist > 3. || !self.fidata.do_coasting {
+            return BuyEval::BUY;
+        }
+        match id {
+            0 => self.eval_coast_one(dist, 0.4, 1.2),
+            1 => self.eval_coast_one(dist, 0.2, 0.8),
+            2 => self.eval_coast_one(dist, 0.3, 1.),
+            _ => BuyEval::SKIP,
+        }
+    }
+
+    fn eval_ratio(&self, id: usize) -> BuyEval {
+        if match id {
+            0 => {
+                self.vars.q1.cost + (7. + (self.vars.q1.level % 10) as f64).log10()
+                    < self.vars.q2.cost
+            }
+            1 => true,
+            2 => {
+                self.vars.k.cost + (3. + (self.vars.k.level % 10) as f64).log10()
+                    < self.vars.q2.cost
+            }
+            _ => false,
+        } {
+            BuyEval::BUY
+        } else {
+            BuyEval::SKIP
+        }
+    }
+
+    fn tick(&mut self) {
+        let lambda: f64 = self.get_lambda();
+
+        self.q = log10add(
+            self.q,
+            self.vars.q1.value * (1. + 0.05 * self.milestones[1] as f64)
+                + self.vars.q2.value
+                + self.dt.log10(),
+        );
+        // Fractional integral of order lambda, taking q as linear over the pub:
+        // I^l[q](t) = q(t) t^l / Gamma(2 + l)
+        self.r = self.q + lambda * (self.t + self.dt / 1.5).log10() - log10_gamma(2. + lambda);
+        self.rho = log10add(
+            self.rho,
+            self.multiplier + self.vars.k.value + self.r + self.dt.log10(),
+        );
+        self.maxrho = self.maxrho.max(self.rho);
+
+        self.t += self.dt / 1.5;
+        self.dt *= self.ddt;
+    }
+
+    pub fn simulate(&mut self) -> SimRes {
+        while self.maxrho < self.goal {
+            self.tick();
+            self.buy();
+            self.check_swap();
+        }
+
+        if self.t < self.best_res.t {
+            SimRes {
+                t: self.t,
+                var_buys: Some(self.varbuys.clone()),
+            }
+        } else {
+            SimRes {
+                t: self.best_res.t,
+                var_buys: self.best_res.var_buys.clone(),
+            }
+        }
+    }
+
+    fn buy(&mut self) {
+        let mut cost: f64;
+        let mut coast_eval: BuyEval;
+        let mut ratio_eval: BuyEval;
+        let variables: [(usize, &str); 3] = [(2, "k"), (1, "q2"), (0, "q1")];
+
+        for (id, name) in variables {
+            if self.vars.get(id).get_level() >= self.fidata.caps[id] {
+                continue;
+            }
+
+            cost = self.vars.get(id).get_cost();
+
+            while self.rho > cost {
+                coast_eval = self.eval_coast(id, cost);
+                ratio_eval = self.eval_ratio(id);
+
+                if coast_eval != BuyEval::SKIP {
+                    if ratio_eval == BuyEval::SKIP {
+                        break;
+                    }
+                    if coast_eval == BuyEval::FORK {
+                        let mut fork: FI = self.fork();
+                        let lvl: u32 = self.vars.get(id).get_level();
+                        fork.fidata.caps[id] = lvl;
+                        let res: SimRes = fork.simulate();
+                        if res.t < self.best_res.t {
+                            self.best_res.t = res.t;
+                            self.best_res.var_buys = res.var_buys;
+                        }
+                    }
+
+                    self.rho = log10sub(self.rho, cost);
+                    self.vars.getm(id).buy();
+                    cost = self.vars.get(id).get_cost();
+
+                    if self.maxrho > self.data.tau * 2.5 - 5. {
+                        self.varbuys.push(VarBuy {
+                            symb: s!(name),
+                            lvl: self.vars.get(id).get_level(),
+                            t: self.t,
+                        })
+                    }
+                } else {
+                    self.fidata.caps[id] = self.vars.get(id).get_level();
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log10_gamma_known_values() {
+        // Gamma(n) = (n - 1)!, Gamma(1/2) = sqrt(pi)
+        for (x, gamma) in [
+            (1., 1.),
+            (2., 1.),
+            (5., 24.),
+            (11., 3_628_800.),
+            (0.5, std::f64::consts::PI.sqrt()),
+            (1.5, std::f64::consts::PI.sqrt() / 2.),
+        ] {
+            assert!(
+                (log10_gamma(x) - f64::log10(gamma)).abs() < 1e-12,
+                "x = {x}"
+            );
+        }
+    }
+
+    #[test]
+    fn log10_gamma_large_arguments() {
+        // log10(100!) = 157.97000365471578
+        assert!((log10_gamma(101.) - 157.97000365471578).abs() < 1e-9);
+        // recurrence Gamma(x + 1) = x Gamma(x)
+        let x: f64 = 1234.5;
+        assert!((log10_gamma(x + 1.) - log10_gamma(x) - x.log10()).abs() < 1e-9);
+    }
+}
@@ -1,6 +1,14 @@
 use crate::s;
 use crate::utils::*;
 
+/// Milestone tree: q term, r term, n terms, s, r exponent and s boost.
+/// Each point unlocks the next milestone in order.
+pub const MILESTONES: Milestones<6> = Milestones {
+    points: &[10., 30., 175., 425., 730., 1500., 1650.],
+    max_levels: [1, 1, 2, 1, 1, 1],
+    order: [0, 1, 2, 3, 4, 5],
+};
+
 pub fn stepwise_sum(mut level: u32, base: u32, length: u32) -> u32 {
     if level <= length {
         return level;
@@ -65,13 +73,16 @@ impl Value for R1Value {
     }
 }
 
+/// s grows by 0.15 per level, 0.2 in the levels boosted by the s boost milestone.
 #[derive(Debug)]
-struct SValue {}
+struct SValue {
+    boost: bool,
+}
 
 impl Value for SValue {
     fn recompute(&self, level: u32) -> f64 {
         let cutoffs = [32, 39];
-        if level < cutoffs[0] {
+        if !self.boost || level < cutoffs[0] {
             1. + level as f64 * 0.15
         } else if level < cutoffs[1] {
             self.recompute(cutoffs[0] - 1) + 0.15 + (level - cutoffs[0]) as f64 * 0.2
@@ -131,10 +142,19 @@ impl FPvars {
                 },
             ),
             n: Variable::new(ExponentialCost::new(1e4, 3e6), EmptyValue {}),
-            s: Variable::new(ExponentialCost::new_fullbase(730., 1e30), SValue {}),
+            s: Variable::new(
+                ExponentialCost::new_fullbase(730., 1e30),
+                SValue { boost: true },
+            ),
         }
     }
 
+    fn update_s_boost(&mut self, boost: bool) {
+        let level = self.s.level;
+        self.s = Variable::new(ExponentialCost::new_fullbase(730., 1e30), SValue { boost });
+        self.s.set(level);
+    }
+
     fn getm(&mut self, id: usize) -> &mut dyn VariableTrait {
         match id {
             0 => &mut self.c1,
@@ -170,6 +190,10 @@ impl FPvars {
 pub struct FPdata {
     caps: [u32; 7],
     pub do_coasting: bool,
+    /// Milestone levels, in `MILESTONES` order.
+    pub milestones: [u32; 6],
+    /// Derive the milestones from rho and tau instead of keeping the given ones.
+    pub derive_milestones: bool,
 }
 
 impl Copy for FPdata {}
@@ -183,6 +207,8 @@ pub struct FPstate {
 
 #[derive(Debug, Clone, Copy)]
 struct FPcache {
+    /// Milestones the cache and s were last updated for.
+    ms: [u32; 6],
     n: u32,
     tn: f64,
     un: f64,
@@ -206,8 +232,6 @@ pub struct FP {
     vars: FPvars,
     varbuys: Vec<VarBuy>,
 
-    rmilestone: bool,
-
     t: f64,
     dt: f64,
     ddt: f64,
@@ -223,6 +247,8 @@ impl FP {
             fpdata: FPdata {
                 caps: [u32::MAX; 7],
                 do_coasting: true,
+                milestones: [0; 6],
+                derive_milestones: true,
             },
             goal: goal,
             rho: 0.,
@@ -233,6 +259,7 @@ impl FP {
             q: 0.,
             r: 0.,
             cache: FPcache {
+                ms: [u32::MAX; 6],
                 n: 1,
                 tn: 0.,
                 un: 0.,
@@ -241,7 +268,6 @@ impl FP {
             update_cache: true,
             vars: FPvars::init(),
             varbuys: Vec::new(),
-            rmilestone: false,
 
             t: 0.,
             dt: 1.5,
@@ -283,7 +309,6 @@ impl FP {
             update_cache: self.update_cache,
             vars: FPvars::init(),
             varbuys: self.varbuys.clone(),
-            rmilestone: self.rmilestone,
 
             t: self.t,
             dt: self.dt,
@@ -296,6 +321,7 @@ impl FP {
         for i in 0..7 {
             new.vars.getm(i).set(self.vars.get(i).get_level())
         }
+        new.vars.update_s_boost(new.fpdata.milestones[5] >= 1);
 
         new
     }
@@ -304,6 +330,29 @@ impl FP {
         tau * 0.331 + 5f64.log10()
     }
 
+    /// Milestones reached with the max of rho and the last pub's rho.
+    fn update_milestones(&mut self) {
+        if self.fpdata.derive_milestones {
+            let rho = self.maxrho.max(self.data.tau * (1. / 0.3));
+            self.fpdata.milestones = MILESTONES.at(rho);
+        }
+        if self.fpdata.milestones != self.cache.ms {
+            self.cache.ms = self.fpdata.milestones;
+            self.vars.update_s_boost(self.fpdata.milestones[5] >= 1);
+            self.update_cache = true;
+        }
+    }
+
+    fn get_variable_conditions(&self, id: usize) -> bool {
+        match id {
+            0 | 1 | 5 => true,
+            2 | 3 => self.fpdata.milestones[0] >= 1,
+            4 => self.fpdata.milestones[1] >= 1,
+            6 => self.fpdata.milestones[3] >= 1,
+            _ => false,
+        }
+    }
+
     fn eval_coast_one(&self, dist: f64, lbound: f64, ubound: f64) -> BuyEval {
         if dist > ubound {
             BuyEval::BUY
@@ -405,40 +454,51 @@ impl FP {
 
     fn tick(&mut self) {
         if self.update_cache {
-            self.cache.n = (1
-                + stepwise_sum(self.vars.n.level, 1, 40)
-                + stepwise_sum((self.vars.n.level as i32 - 30).max(0) as u32, 1, 35) * 2
-                + (stepwise_sum((self.vars.n.level as i32 - 69).max(0) as u32, 1, 30) as f64 * 2.4
+            let n_terms = self.fpdata.milestones[2];
+            let mut n = 1 + stepwise_sum(self.vars.n.level, 1, 40);
+            if n_terms >= 1 {
+                n += stepwise_sum((self.vars.n.level as i32 - 30).max(0) as u32, 1, 35) * 2;
+            }
+            if n_terms >= 2 {
+                n += (stepwise_sum((self.vars.n.level as i32 - 69).max(0) as u32, 1, 30) as f64
+                    * 2.4
                     + 0.001)
-                    .floor() as u32)
-                .min(20000);
+                    .floor() as u32;
+            }
+            self.cache.n = n.min(20000);
             self.cache.tn = self.get_t(self.cache.n);
             self.cache.un = self.get_u(self.cache.n);
             self.cache.sn = self.get_s((self.cache.n as f64 + 0.001).sqrt().floor() as u32);
             self.update_cache = false;
         }
 
+        let ms = self.fpdata.milestones;
+
         self.tvar += self.dt;
-        self.q = log10add(
-            self.q,
-            self.vars.q1.value
-                + self.approx(self.vars.q2.level)
-                + self.cache.un.log10() * (7. + self.vars.s.value)
-                - 3.
-                + self.dt.log10(),
-        );
-        self.r = log10add(
-            self.r,
-            self.vars.r1.value
-                + (self.cache.tn.log10() + self.cache.un.log10())
-                    * (if self.rmilestone {
-                        (self.cache.un * 2.).log10() / 2.
-                    } else {
-                        (self.cache.n as f64).log10()
-                    })
-                + self.cache.sn * 2.8
-                + self.dt.log10(),
-        );
+        if ms[0] >= 1 {
+            self.q = log10add(
+                self.q,
+                self.vars.q1.value
+                    + self.approx(self.vars.q2.level)
+                    + self.cache.un.log10() * (7. + self.vars.s.value)
+                    - 3.
+                    + self.dt.log10(),
+            );
+        }
+        if ms[1] >= 1 {
+            self.r = log10add(
+                self.r,
+                self.vars.r1.value
+                    + (self.cache.tn.log10() + self.cache.un.log10())
+                        * (if ms[4] >= 1 {
+                            (self.cache.un * 2.).log10() / 2.
+                        } else {
+                            (self.cache.n as f64).log10()
+                        })
+                    + self.cache.sn * 2.8
+                    + self.dt.log10(),
+            );
+        }
         self.rho = log10add(
             self.rho,
             self.multiplier
@@ -447,8 +507,8 @@ impl FP {
                 + self.cache.tn.log10() * (5. + self.vars.s.value)
                 + self.tvar.log10()
                 + self.dt.log10()
-                + self.q
-                + self.r,
+                + if ms[0] >= 1 { self.q } else { 0. }
+                + if ms[1] >= 1 { self.r } else { 0. },
         );
 
         self.maxrho = self.maxrho.max(self.rho);
@@ -459,9 +519,7 @@ impl FP {
 
     pub fn simulate(&mut self) -> SimRes {
         while self.maxrho < self.goal {
-            if self.rho.max(self.data.tau * (1. / 0.3)) >= 1500. {
-                self.rmilestone = true;
-            }
+            self.update_milestones();
             self.tick();
             self.buy();
         }
@@ -489,7 +547,9 @@ impl FP {
 
         for i in 0..7 {
             //if self.t2data.skip[ids[i]] { continue; }
-            if self.vars.get(ids[i]).get_level() >= self.fpdata.caps[ids[i]] {
+            if self.vars.get(ids[i]).get_level() >= self.fpdata.caps[ids[i]]
+                || !self.get_variable_conditions(ids[i])
+            {
                 continue;
             }
             cost = self.vars.get(ids[i]).get_cost();
@@ -1,30 +1,33 @@
+mod bap;
+mod bt;
 mod csr2;
 mod de;
 mod ef;
+mod fi;
 mod fp;
 mod ioutils;
+mod mf;
+mod pubtable;
+mod rz;
+mod sl;
 mod t1;
 mod t2;
 mod t6;
 mod t7;
 pub mod utils;
 use ioutils::*;
+use pubtable::*;
 use utils::*;
 
 use std::error::Error;
 use std::fs;
 use std::io;
+use std::ops::Range;
 use std::path::Path;
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::{collections::HashMap, fmt::Display};
 
 //use serde::de;
-use serde::{Deserialize, Serialize};
-
-#[derive(Debug, Serialize, Deserialize)]
-struct PubData {
-    next: u32,
-    t: f64,
-}
 
 fn input_theory_data() -> Result<TheoryData, Box<dyn Error>> {
     Ok(TheoryData {
@@ -44,12 +47,18 @@ fn input_levels<const N: usize>(names: &[impl Display; N]) -> Result<[u32; N], B
     Ok(levels)
 }
 
+fn input_milestones<const N: usize>(
+    names: &[impl Display; N],
+    milestones: &Milestones<N>,
+) -> Result<[u32; N], Box<dyn Error>> {
+    Ok(milestones.validate(input_levels(names)?)?)
+}
+
 fn compress_pub_tables(source: String, dest: String) -> Result<(), Box<dyn Error>> {
     let source_path = Path::new(&source);
     let dest_path = Path::new(&dest);
 
-    let content = fs::read_to_string(source_path)?;
-    let pub_table: HashMap<u32, PubData> = serde_json::from_str(&content)?;
+    let pub_table = PubTable::read(source_path)?.entries;
     let mut dest_data: HashMap<u32, u32> = HashMap::new();
 
     for key in pub_table.keys() {
@@ -70,8 +79,7 @@ fn compress_pub_tables(source: String, dest: String) -> Result<(), Box<dyn Error
 
 fn get_pub_tables_range(source: String, end: u32) -> Result<(), Box<dyn Error>> {
     let path = Path::new(&source);
-    let content = fs::read_to_string(path)?;
-    let pub_table: HashMap<u32, PubData> = serde_json::from_str(&content)?;
+    let pub_table = PubTable::read(path)?.entries;
     let mut low = u32::MAX;
     let mut high = u32::MIN;
     let mut dist: u32;
@@ -94,11 +102,11 @@ fn get_pub_tables_diff(
     source: String,
     start: u32,
     end: u32,
-    grid: f64,
+    grid: u32,
 ) -> Result<(), Box<dyn Error>> {
     let path = Path::new(&source);
-    let content = fs::read_to_string(path)?;
-    let pub_table: HashMap<u32, PubData> = serde_json::from_str(&content)?;
+    let pub_table = PubTable::read_grid(path, grid)?.entries;
+    let grid = grid as f64;
 
     for i in start..=end {
         match pub_table.get(&i) {
@@ -129,6 +137,25 @@ fn sim_t1() -> t1::T1 {
     sim
 }
 
+fn sim_t1_input() -> Result<t1::T1, Box<dyn Error>> {
+    let theory_data = input_theory_data()?;
+    let goal = strtolog10(&input("Input goal: ")?);
+    let strat: t1::T1strat = input("Input strategy (c34, ratio, c4, idle): ")?.parse()?;
+
+    let levels = input_levels(&["q1", "q2", "c3", "c4", "c1", "c2"])?;
+    let milestones = input_milestones(
+        &["log term milestone", "c3 milestone", "c4 milestone"],
+        &t1::MILESTONES,
+    )?;
+
+    let mut sim: t1::T1 = t1::T1::new(theory_data, goal, Some(t1::T1state { levels }));
+    sim.t1data.strat = strat;
+    sim.t1data.milestones = milestones;
+    sim.t1data.derive_milestones = false;
+
+    Ok(sim)
+}
+
 fn sim_t7() {
     let mut sim: t7::T7 = t7::T7::new(
         TheoryData {
@@ -145,9 +172,36 @@ fn sim_t7() {
     println!("{}", get_time_string(res.t));
 }
 
+fn sim_t7_input() -> Result<t7::T7, Box<dyn Error>> {
+    let theory_data = input_theory_data()?;
+    let goal = strtolog10(&input("Input goal: ")?);
+    let strat: t7::T7strat = input("Input strategy (ratio, c6, fork, autobuy): ")?.parse()?;
+
+    let levels = input_levels(&["q1", "c3", "c4", "c5", "c6", "q2", "c1", "c2"])?;
+    let rho2 = strtolog10(&input("Input rho2: ")?);
+    let milestones = input_milestones(
+        &[
+            "c1 exponent milestone",
+            "c3 milestone",
+            "c4 milestone",
+            "c5 milestone",
+            "c6 milestone",
+        ],
+        &t7::MILESTONES,
+    )?;
+
+    let mut sim: t7::T7 = t7::T7::new(theory_data, goal, Some(t7::T7state { levels, rho2 }));
+    sim.t7data.strat = strat;
+    sim.t7data.milestones = milestones;
+    sim.t7data.derive_milestones = false;
+
+    Ok(sim)
+}
+
 fn sim_t2_input() -> Result<t2::T2, Box<dyn Error>> {
     let theory_data = input_theory_data()?;
     let goal = strtolog10(&input("Input goal: ")?);
+    let strat: t2::T2strat = input("Input strategy (buyall, ratio): ")?.parse()?;
 
     let layer_names = ["q1", "q2", "q3", "q4", "r1", "r2", "r3", "r4"];
     let var_names = layer_names.map(|s| "d".to_owned() + s);
@@ -159,18 +213,59 @@ fn sim_t2_input() -> Result<t2::T2, Box<dyn Error>> {
         layers[i] = strtolog10(&input(&format!("Input layer {}: ", name))?);
     }
 
-    let sim: t2::T2 = t2::T2::new(theory_data, goal, Some(t2::T2state { levels, layers }));
+    let mut sim: t2::T2 = t2::T2::new(theory_data, goal, Some(t2::T2state { levels, layers }));
+    sim.t2data.strat = strat;
+    let points: u32 = input("Input milestone points: ")?.parse()?;
+    let max_points = t2::MILESTONES.total();
+    if points > max_points {
+        return Err(format!("{points} milestone points, T2 has {max_points}").into());
+    }
+    sim.t2data.points = points;
+    sim.t2data.derive_milestones = false;
 
     Ok(sim)
 }
 
+/// Prints the time of a fresh T2 pub from tau to tau + `pub_len` for each strategy.
+fn t2_compare_strats(students: u32, taus: impl Iterator<Item = u32>, pub_len: f64) {
+    let strats = [t2::T2strat::BuyAll, t2::T2strat::Ratio];
+
+    for tau in taus {
+        let times = strats.map(|strat| {
+            let mut sim = t2::T2::new(
+                TheoryData {
+                    tau: tau as f64,
+                    students,
+                    rho: 0.,
+                },
+                tau as f64 + pub_len,
+                None,
+            );
+            sim.t2data.strat = strat;
+            sim.simulate().t
+        });
+
+        println!(
+            "tau {tau}: {}",
+            strats
+                .iter()
+                .zip(times)
+                .map(|(strat, t)| format!("{} {}", strat.name(), get_time_string(t)))
+                .collect::<Vec<String>>()
+                .join(", ")
+        );
+    }
+}
+
 fn sim_t6_input() -> Result<t6::T6, Box<dyn Error>> {
     let theory_data = input_theory_data()?;
     let goal = strtolog10(&input("Input goal: ")?);
+    let strat: t6::T6strat = input("Input strategy (c5, c125, noc1234, ratio): ")?.parse()?;
 
     let levels = input_levels(&["q1", "q2", "r1", "r2", "c1", "c2", "c5"])?;
+    let milestones = input_milestones(&["c1 exponent milestone"], &t6::MILESTONES)?;
 
-    let sim: t6::T6 = t6::T6::new(
+    let mut sim: t6::T6 = t6::T6::new(
         theory_data,
         goal,
         Some(t6::T6state {
@@ -178,8 +273,10 @@ fn sim_t6_input() -> Result<t6::T6, Box<dyn Error>> {
             q: strtolog10(&input("Input q: ")?),
             r: strtolog10(&input("Input r: ")?),
             tol: input("Input tol: ")?.parse()?,
+            milestones,
         }),
     );
+    sim.t6data.strat = strat;
 
     Ok(sim)
 }
@@ -198,12 +295,32 @@ fn sim_ef() -> ef::EF {
     sim
 }
 
+fn sim_csr2_input() -> Result<csr2::CSR2, Box<dyn Error>> {
+    let theory_data = input_theory_data()?;
+    let goal = strtolog10(&input("Input goal: ")?);
+    let strat: csr2::CSR2strat = input("Input strategy (active, idle, swap): ")?.parse()?;
+
+    let levels = input_levels(&["q1", "q2", "c1", "n", "c2"])?;
+    let q = strtolog10(&input("Input q: ")?);
+    let milestones = input_milestones(
+        &["q1 exponent milestone", "c2 error term milestone"],
+        &csr2::MILESTONES,
+    )?;
+
+    let mut sim: csr2::CSR2 =
+        csr2::CSR2::new(theory_data, goal, Some(csr2::CSR2state { levels, q }));
+    sim.csr2data.strat = strat;
+    sim.csr2data.milestones = milestones;
+    sim.csr2data.derive_milestones = false;
+
+    Ok(sim)
+}
+
 fn sim_csr2(rho: f64) -> Result<csr2::CSR2, Box<dyn Error>> {
-    let path = Path::new("data/csr2.json");
-    let content = fs::read_to_string(path)?;
-    let pub_table: HashMap<u32, PubData> = serde_json::from_str(&content)?;
+    let path = Path::new("data/csr2.bin");
+    let pub_table = PubTableBin::open_grid(path, 16)?;
     let seek = (rho * 16.).round() as u32;
-    let goal: u32 = match pub_table.get(&seek) {
+    let goal: u32 = match pub_table.get(seek) {
         None => {
             return Err(Box::new(std::io::Error::new(
                 std::io::ErrorKind::NotFound,
@@ -224,6 +341,36 @@ fn sim_csr2(rho: f64) -> Result<csr2::CSR2, Box<dyn Error>> {
     ))
 }
 
+fn sim_fp_input() -> Result<fp::FP, Box<dyn Error>> {
+    let theory_data = input_theory_data()?;
+    let goal = strtolog10(&input("Input goal: ")?);
+
+    let levels = input_levels(&["c1", "c2", "q1", "q2", "r1", "n", "s"])?;
+    let state = fp::FPstate {
+        levels,
+        t: input("Input t: ")?.parse()?,
+        q: strtolog10(&input("Input q: ")?),
+        r: strtolog10(&input("Input r: ")?),
+    };
+    let milestones = input_milestones(
+        &[
+            "q term milestone",
+            "r term milestone",
+            "n terms milestone",
+            "s milestone",
+            "r exponent milestone",
+            "s boost milestone",
+        ],
+        &fp::MILESTONES,
+    )?;
+
+    let mut sim: fp::FP = fp::FP::new(theory_data, goal, Some(state));
+    sim.fpdata.milestones = milestones;
+    sim.fpdata.derive_milestones = false;
+
+    Ok(sim)
+}
+
 fn sim_fp() -> fp::FP {
     let sim: fp::FP = fp::FP::new(
         TheoryData {
@@ -238,6 +385,28 @@ fn sim_fp() -> fp::FP {
     sim
 }
 
+fn sim_de_input() -> Result<de::DE, Box<dyn Error>> {
+    let theory_data = input_theory_data()?;
+    let goal = strtolog10(&input("Input goal: ")?);
+    let strat: de::DEstrat = input("Input strategy (base, a2a0): ")?.parse()?;
+
+    let levels = input_levels(&["n", "m", "a0", "a1", "a2", "max x"])?;
+    let state = de::DEstate {
+        levels,
+        tvar: strtolog10(&input("Input t: ")?),
+        x: strtolog10(&input("Input x: ")?),
+        q: strtolog10(&input("Input q: ")?),
+    };
+    let milestones = input_milestones(&["n exponent milestone", "a2 milestone"], &de::MILESTONES)?;
+
+    let mut sim: de::DE = de::DE::new(theory_data, goal, Some(state));
+    sim.dedata.strat = strat;
+    sim.dedata.milestones = milestones;
+    sim.dedata.derive_milestones = false;
+
+    Ok(sim)
+}
+
 fn sim_de() -> de::DE {
     let sim: de::DE = de::DE::new(
         TheoryData {
@@ -252,505 +421,635 @@ fn sim_de() -> de::DE {
     sim
 }
 
-fn t1_pub_tables() -> Result<(), Box<dyn Error>> {
-    let path = Path::new("data/t1c34.json");
-
-    const CTEND: u32 = 900 * 32;
-
-    let content = fs::read_to_string(path)?;
-
-    let mut pub_data: HashMap<u32, PubData> = serde_json::from_str(&content)?;
-
-    println!("Read");
-    //println!("{:?}", pub_data.get(&24000));
-
-    let mut best_t: f64;
-    let mut best_next: u32;
-    let mut sim: t1::T1;
-    let mut simbase: t1::T1;
-    let mut simt: f64;
-    let mut a: u32;
-    let mut b: u32;
-    let mut end_t: f64;
-
-    for start in ((800 * 32)..(900 * 32)).rev() {
-        println!("Starting pub tables for {}", start as f64 / 32.);
-        best_t = f64::MAX;
-        best_next = 0;
-        a = 40.min(CTEND - start);
-        b = 150.min(CTEND - start);
-
-        simbase = t1::T1::new(
-            TheoryData {
-                tau: start as f64 / 32.,
-                students: 500,
-                rho: 0.,
-            },
-            (start + a) as f64 / 32. - 6.,
-            None,
-        );
-        simbase.t1data.do_coasting = false;
-
-        for end in (start + a)..(start + b + 1) {
-            simbase.goal = end as f64 / 32. - 6.;
-            simbase.simulate();
+/// Where a pub table is generated. `ctend` is the last pub of the table, seeded as
+/// finished; rows between `rows` and `ctend` must already be in the table.
+struct PubTableRun<'a> {
+    path: &'a Path,
+    meta: PubMeta,
+    rows: Range<u32>,
+    ctend: u32,
+}
 
-            sim = simbase.fork();
-            sim.t1data.do_coasting = true;
-            sim.goal = end as f64 / 32.;
-            simt = sim.simulate().t;
+/// Generates the rows of a pub table from the top down. For each start, `new_base` makes
+/// the sim of the pub with coasting off, and `fork_at` advances it to the coasting point of
+/// a candidate next pub and returns a coasting fork there. The forks are simulated in
+/// parallel and the candidate with the least total time to `ctend` is kept. `window` gives
+/// the candidates, in grid steps above the start.
+fn pub_tables<S: Send>(
+    run: PubTableRun,
+    window: impl Fn(u32) -> [u32; 2],
+    new_base: impl Fn(u32) -> S,
+    fork_at: impl Fn(&mut S, u32) -> S,
+    simulate: impl Fn(S) -> f64 + Sync,
+) -> Result<(), Box<dyn Error>> {
+    let PubTableRun {
+        path,
+        meta,
+        rows,
+        ctend,
+    } = run;
+    let grid = meta.grid as f64;
+    let ends = |start: u32| {
+        let [a, b] = window(start);
+        (start + a.min(ctend - start))..=(start + b.min(ctend - start))
+    };
 
-            end_t = match pub_data.get(&end) {
-                None => 1e100,
-                Some(pdata) => pdata.t,
-            };
+    let mut table = PubTable::open(path, meta)?;
+    table
+        .entries
+        .entry(ctend)
+        .or_insert(PubData { next: ctend, t: 0. });
+
+    if rows.end > ctend {
+        return Err(Box::new(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("rows end above the last pub {}", ctend as f64 / grid),
+        )));
+    }
+    let missing = rows
+        .clone()
+        .flat_map(ends)
+        .filter(|next| *next >= rows.end && !table.entries.contains_key(next))
+        .min();
+    if let Some(next) = missing {
+        return Err(Box::new(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{} has no entry for {}, generate the rows above {} first",
+                path.display(),
+                next as f64 / grid,
+                rows.end as f64 / grid
+            ),
+        )));
+    }
 
-            if simt + end_t < best_t {
-                best_t = simt + end_t;
-                best_next = end;
+    for start in table.rows_left(&rows) {
+        println!("Starting pub tables for {}", start as f64 / grid);
+
+        let mut simbase = new_base(start);
+        let forks: Vec<S> = ends(start)
+            .map(|next| fork_at(&mut simbase, next))
+            .collect();
+        let count = forks.len();
+        let done = AtomicU32::new(0);
+        let times = par_map(forks, |sim| {
+            let simt = simulate(sim);
+            print!(
+                "\rTesting sim {}/{}",
+                done.fetch_add(1, Ordering::Relaxed) + 1,
+                count
+            );
+            let _ = io::Write::flush(&mut io::stdout());
+            simt
+        });
+
+        let mut best = PubData {
+            next: 0,
+            t: f64::MAX,
+        };
+        for (next, simt) in ends(start).zip(times) {
+            let t = simt + table.entries[&next].t;
+            if t < best.t {
+                best = PubData { next, t };
             }
         }
 
-        pub_data.insert(
-            start,
-            PubData {
-                next: best_next,
-                t: best_t,
-            },
+        println!();
+        println!(
+            "Best next: {} ; Total time remaining: {} ; Index diff: {}",
+            best.next as f64 / grid,
+            get_time_string(best.t),
+            best.next - start
         );
-    }
 
-    //pub_data.insert(23999, PubData { next: 24000, t: 0.1 });
-
-    let updated_content = serde_json::to_string_pretty(&pub_data)?;
-    fs::write(path, updated_content)?;
+        table.entries.insert(start, best);
+        table.complete_row(path, &rows, start)?;
+    }
 
-    Ok(())
+    table.finish(path)
 }
 
-fn t7_pub_tables() -> Result<(), Box<dyn Error>> {
-    let path = Path::new("data/t7.json");
-
-    const CTEND: u32 = 800 * 32;
+fn t1_pub_tables(strat: t1::T1strat) -> Result<(), Box<dyn Error>> {
+    const WINDOW: [u32; 2] = [40, 150];
 
-    let content = fs::read_to_string(path)?;
+    let path = format!("data/t1{}.json", strat.name());
 
-    let mut pub_data: HashMap<u32, PubData> = serde_json::from_str(&content)?;
-
-    println!("Read");
-    //println!("{:?}", pub_data.get(&24000));
+    pub_tables(
+        PubTableRun {
+            path: Path::new(&path),
+            meta: PubMeta::new("t1", 32, 500, Some(strat.name()), 6., WINDOW),
+            rows: (800 * 32)..(900 * 32),
+            ctend: 900 * 32,
+        },
+        |_| WINDOW,
+        |start| {
+            let mut sim = t1::T1::new(
+                TheoryData {
+                    tau: start as f64 / 32.,
+                    students: 500,
+                    rho: 0.,
+                },
+                0.,
+                None,
+            );
+            sim.t1data.do_coasting = false;
+            sim.t1data.strat = strat;
+            sim
+        },
+        |simbase, end| {
+            simbase.goal = end as f64 / 32. - 6.;
+            simbase.simulate();
 
-    let mut best_t: f64;
-    let mut best_next: u32;
-    let mut sim: t7::T7;
-    let mut simbase: t7::T7;
-    let mut simt: f64;
-    let mut a: u32;
-    let mut b: u32;
-    let mut end_t: f64;
+            let mut sim = simbase.fork();
+            sim.t1data.do_coasting = true;
+            sim.goal = end as f64 / 32.;
+            sim
+        },
+        |mut sim| sim.simulate().t,
+    )
+}
 
-    for start in ((700 * 32)..(800 * 32)).rev() {
-        println!("Starting pub tables for {}", start as f64 / 32.);
-        best_t = f64::MAX;
-        best_next = 0;
-        a = 40.min(CTEND - start);
-        b = 128.min(CTEND - start);
+fn t7_pub_tables(strat: t7::T7strat) -> Result<(), Box<dyn Error>> {
+    const WINDOW: [u32; 2] = [40, 128];
 
-        simbase = t7::T7::new(
-            TheoryData {
-                tau: start as f64 / 32.,
-                students: 500,
-                rho: 0.,
-            },
-            (start + a) as f64 / 32. - 1.5,
-            None,
-        );
-        simbase.t7data.do_coasting = false;
+    let path = format!("data/t7{}.json", strat.name());
 
-        for end in (start + a)..(start + b + 1) {
+    pub_tables(
+        PubTableRun {
+            path: Path::new(&path),
+            meta: PubMeta::new("t7", 32, 500, Some(strat.name()), 1.5, WINDOW),
+            rows: (700 * 32)..(800 * 32),
+            ctend: 800 * 32,
+        },
+        |_| WINDOW,
+        |start| {
+            let mut sim = t7::T7::new(
+                TheoryData {
+                    tau: start as f64 / 32.,
+                    students: 500,
+                    rho: 0.,
+                },
+                0.,
+                None,
+            );
+            sim.t7data.strat = strat;
+            sim.t7data.do_coasting = false;
+            sim
+        },
+        |simbase, end| {
             simbase.goal = end as f64 / 32. - 1.5;
             simbase.simulate();
 
-            sim = simbase.fork();
+            let mut sim = simbase.fork();
             sim.t7data.do_coasting = true;
             sim.goal = end as f64 / 32.;
-            simt = sim.simulate().t;
-
-            end_t = match pub_data.get(&end) {
-                None => 1e100,
-                Some(pdata) => pdata.t,
-            };
-
-            if simt + end_t < best_t {
-                best_t = simt + end_t;
-                best_next = end;
-            }
-        }
-
-        pub_data.insert(
-            start,
-            PubData {
-                next: best_next,
-                t: best_t,
-            },
-        );
-    }
-
-    //pub_data.insert(23999, PubData { next: 24000, t: 0.1 });
-
-    let updated_content = serde_json::to_string_pretty(&pub_data)?;
-    fs::write(path, updated_content)?;
-
-    Ok(())
+            sim
+        },
+        |mut sim| sim.simulate().t,
+    )
 }
 
-fn csr2_pub_tables() -> Result<(), Box<dyn Error>> {
-    let path = Path::new("data/csr2.json");
-
-    const CTEND: u32 = 1500 * 16;
-
-    let content = fs::read_to_string(path)?;
+fn csr2_pub_tables(strat: csr2::CSR2strat) -> Result<(), Box<dyn Error>> {
+    const WINDOW: [u32; 2] = [8, 80];
 
-    let mut pub_data: HashMap<u32, PubData> = serde_json::from_str(&content)?;
-
-    println!("Read");
-    //println!("{:?}", pub_data.get(&24000));
-
-    let mut best_t: f64;
-    let mut best_next: u32;
-    let mut sim: csr2::CSR2;
-    let mut simbase: csr2::CSR2;
-    let mut simt: f64;
-    let mut a: u32;
-    let mut b: u32;
-    let mut end_t: f64;
-
-    for start in ((500 * 16)..(700 * 16)).rev() {
-        println!("Starting pub tables for {}", start as f64 / 16.);
-        best_t = f64::MAX;
-        best_next = 0;
-        a = 8.min(CTEND - start);
-        b = 80.min(CTEND - start);
-
-        simbase = csr2::CSR2::new(
-            TheoryData {
-                tau: start as f64 * 0.4 / 16.,
-                students: 0,
-                rho: 0.,
-            },
-            (start + a) as f64 / 16. - 1.8,
-            None,
-        );
-        simbase.csr2data.do_coasting = false;
+    // active is the original table that csr2.bin is built from
+    let path = match strat {
+        csr2::CSR2strat::Active => s!("data/csr2.json"),
+        _ => format!("data/csr2{}.json", strat.name()),
+    };
+    // swaps only change anything until every milestone is reached at rho 115
+    let (rows, ctend) = match strat {
+        csr2::CSR2strat::Swap => ((10 * 16)..(120 * 16), 125 * 16),
+        _ => ((500 * 16)..(700 * 16), 1500 * 16),
+    };
 
-        for end in (start + a)..(start + b + 1) {
+    pub_tables(
+        PubTableRun {
+            path: Path::new(&path),
+            meta: PubMeta::new("csr2", 16, 0, Some(strat.name()), 1.8, WINDOW),
+            rows,
+            ctend,
+        },
+        |_| WINDOW,
+        |start| {
+            let mut sim = csr2::CSR2::new(
+                TheoryData {
+                    tau: start as f64 * 0.4 / 16.,
+                    students: 0,
+                    rho: 0.,
+                },
+                0.,
+                None,
+            );
+            // the prefix keeps the q1 exponent, swaps are searched in each fork
+            sim.csr2data.strat = match strat {
+                csr2::CSR2strat::Swap => csr2::CSR2strat::Active,
+                _ => strat,
+            };
+            sim.csr2data.do_coasting = false;
+            sim
+        },
+        |simbase, end| {
             simbase.goal = end as f64 / 16. - 1.8;
             simbase.simulate();
 
-            sim = simbase.fork();
+            let mut sim = simbase.fork();
+            sim.csr2data.strat = strat;
             sim.csr2data.do_coasting = true;
             sim.goal = end as f64 / 16.;
-            simt = sim.simulate().t;
-
-            end_t = match pub_data.get(&end) {
-                None => 1e100,
-                Some(pdata) => pdata.t,
-            };
-
-            if simt + end_t < best_t {
-                best_t = simt + end_t;
-                best_next = end;
-            }
-        }
+            sim
+        },
+        |mut sim| sim.simulate().t,
+    )?;
 
-        pub_data.insert(
-            start,
-            PubData {
-                next: best_next,
-                t: best_t,
-            },
-        );
+    // sim_csr2 reads the binary form, rebuilt here so it never lags the table
+    if strat == csr2::CSR2strat::Active {
+        pub_table_json_to_bin(Path::new(&path), Path::new("data/csr2.bin"))?;
     }
 
-    //pub_data.insert(23999, PubData { next: 24000, t: 0.1 });
-
-    let updated_content = serde_json::to_string_pretty(&pub_data)?;
-    fs::write(path, updated_content)?;
-
     Ok(())
 }
 
 fn fp_pub_tables() -> Result<(), Box<dyn Error>> {
-    let path = Path::new("data/fp.json");
-
-    const CTEND: u32 = 2000 * 8;
-
-    let content = fs::read_to_string(path)?;
-
-    let mut pub_data: HashMap<u32, PubData> = serde_json::from_str(&content)?;
-
-    println!("Read");
-    //println!("{:?}", pub_data.get(&24000));
-
-    let mut best_t: f64;
-    let mut best_next: u32;
-    let mut sim: fp::FP;
-    let mut simbase: fp::FP;
-    let mut simt: f64;
-    let mut a: u32;
-    let mut b: u32;
-    let mut end_t: f64;
-
-    for start in ((1200 * 8)..(1300 * 8)).rev() {
-        println!("Starting pub tables for {}", start as f64 / 8.);
-        best_t = f64::MAX;
-        best_next = 0;
-        a = 40.min(CTEND - start);
-        b = 350.min(CTEND - start);
-
-        simbase = fp::FP::new(
-            TheoryData {
-                tau: start as f64 * 0.3 / 8.,
-                students: 0,
-                rho: 0.,
-            },
-            (start + a) as f64 / 8. - 1.8,
-            None,
-        );
-        simbase.fpdata.do_coasting = false;
-
-        for end in (start + a)..(start + b + 1) {
+    const WINDOW: [u32; 2] = [40, 350];
+
+    pub_tables(
+        PubTableRun {
+            path: Path::new("data/fp.json"),
+            meta: PubMeta::new("fp", 8, 0, None, 1.8, WINDOW),
+            rows: (1200 * 8)..(1300 * 8),
+            ctend: 2000 * 8,
+        },
+        |_| WINDOW,
+        |start| {
+            let mut sim = fp::FP::new(
+                TheoryData {
+                    tau: start as f64 * 0.3 / 8.,
+                    students: 0,
+                    rho: 0.,
+                },
+                0.,
+                None,
+            );
+            sim.fpdata.do_coasting = false;
+            sim
+        },
+        |simbase, end| {
             simbase.goal = end as f64 / 8. - 1.8;
             simbase.simulate();
 
-            sim = simbase.fork();
+            let mut sim = simbase.fork();
             sim.fpdata.do_coasting = true;
             sim.goal = end as f64 / 8.;
-            simt = sim.simulate().t;
-
-            end_t = match pub_data.get(&end) {
-                None => 1e100,
-                Some(pdata) => pdata.t,
-            };
-
-            if simt + end_t < best_t {
-                best_t = simt + end_t;
-                best_next = end;
-            }
-        }
-
-        pub_data.insert(
-            start,
-            PubData {
-                next: best_next,
-                t: best_t,
-            },
-        );
-    }
-
-    //pub_data.insert(23999, PubData { next: 24000, t: 0.1 });
-
-    let updated_content = serde_json::to_string_pretty(&pub_data)?;
-    fs::write(path, updated_content)?;
-
-    Ok(())
+            sim
+        },
+        |mut sim| sim.simulate().t,
+    )
 }
 
-fn de_pub_tables() -> Result<(), Box<dyn Error>> {
-    let path = Path::new("data/de1050.json");
-
-    const CTEND: u32 = 1050 * 16;
+/// `milestones` fixes the milestone levels for the whole table, `None` derives them from rho.
+fn de_pub_tables(milestones: Option<[u32; 2]>) -> Result<(), Box<dyn Error>> {
+    const WINDOW: [u32; 2] = [8, 6 * 16 + 8];
 
-    let content = fs::read_to_string(path)?;
-
-    let mut pub_data: HashMap<u32, PubData> = serde_json::from_str(&content)?;
-
-    println!("Read");
-    //println!("{:?}", pub_data.get(&24000));
-
-    let mut best_t: f64;
-    let mut best_next: u32;
-    let mut sim: de::DE;
-    let mut simbase: de::DE;
-    let mut simt: f64;
-    let mut a: u32;
-    let mut b: u32;
-    let mut end_t: f64;
-
-    for start in ((900 * 16)..(950 * 16)).rev() {
-        println!("Starting pub tables for {}", start as f64 / 16.);
-        best_t = f64::MAX;
-        best_next = 0;
-        a = 8.min(CTEND - start);
-        b = (6 * 16 + 8).min(CTEND - start);
-
-        simbase = de::DE::new(
-            TheoryData {
-                tau: start as f64 * 0.4 / 16.,
-                students: 0,
-                rho: 0.,
-            },
-            (start + a) as f64 / 16. - 1.8,
-            None,
-        );
-        simbase.dedata.do_coasting = false;
-
-        for end in (start + a)..(start + b + 1) {
-            print!("\rTesting sim {}/{}", end - (start + a) + 1, b - a + 1);
-            io::Write::flush(&mut io::stdout())?;
+    let path = match milestones {
+        None => s!("data/de1050.json"),
+        Some([n, a2]) => format!("data/de1050ms{n}{a2}.json"),
+    };
+    let mut meta = PubMeta::new("de", 16, 0, None, 1.8, WINDOW);
+    meta.params = milestones.map(serde_json::to_value).transpose()?;
+
+    pub_tables(
+        PubTableRun {
+            path: Path::new(&path),
+            meta,
+            rows: (900 * 16)..(950 * 16),
+            ctend: 1050 * 16,
+        },
+        |_| WINDOW,
+        |start| {
+            let mut sim = de::DE::new(
+                TheoryData {
+                    tau: start as f64 * 0.4 / 16.,
+                    students: 0,
+                    rho: 0.,
+                },
+                0.,
+                None,
+            );
+            sim.dedata.do_coasting = false;
+            if let Some(milestones) = milestones {
+                sim.dedata.milestones = milestones;
+                sim.dedata.derive_milestones = false;
+            }
+            sim
+        },
+        |simbase, end| {
             simbase.goal = end as f64 / 16. - 1.8;
             simbase.simulate();
 
-            sim = simbase.fork();
+            let mut sim = simbase.fork();
             sim.dedata.do_coasting = true;
             sim.goal = end as f64 / 16.;
-            simt = sim.simulate().t;
-
-            end_t = match pub_data.get(&end) {
-                None => 1e100,
-                Some(pdata) => pdata.t,
-            };
+            sim
+        },
+        |mut sim| sim.simulate().t,
+    )
+}
 
-            if simt + end_t < best_t {
-                best_t = simt + end_t;
-                best_next = end;
+/// With `swap_search`, the prefix keeps the b2/c2 allocation and each fork searches
+/// the swap to the a exponent over its whole run, which starts `swap_window` below the end.
+fn ef_pub_tables(
+    strat: ef::EFstrat,
+    thresholds: ef::EFthresholds,
+    swap_search: bool,
+) -> Result<(), Box<dyn Error>> {
+    let name = format!("{}{}", strat.name(), if swap_search { "swap" } else { "" });
+    let path = format!("data/ef{name}.json");
+    let coast_offset: f64 = if swap_search { 4. } else { 2. };
+
+    pub_tables(
+        PubTableRun {
+            path: Path::new(&path),
+            meta: PubMeta {
+                window: None,
+                params: Some(serde_json::to_value(thresholds)?),
+                ..PubMeta::new("ef", 32, 0, Some(&name), coast_offset, [8, 175])
+            },
+            rows: 0..(5 * 32),
+            ctend: 375 * 32,
+        },
+        // early rows search next pubs from rho 10 to 13
+        |start| {
+            if start >= 15 * 32 {
+                [8, 175]
+            } else {
+                [1.max(10 * 32 - start as i32) as u32, 13 * 32 - start]
             }
-        }
-
-        println!();
-        println!(
-            "Best next: {} ; Total time remaining: {}",
-            best_next as f64 / 16.,
-            get_time_string(best_t)
-        );
+        },
+        |start| {
+            let mut sim = ef::EF::new(
+                TheoryData {
+                    tau: start as f64 * 1.6 / 32.,
+                    students: 0,
+                    rho: 0.,
+                },
+                0.,
+                None,
+            );
+            sim.efdata.thresholds = thresholds;
+            sim.efdata.do_coasting = false;
+            if swap_search {
+                sim.efdata.swap_dist = f64::MIN;
+                sim.efdata.swap_window = coast_offset;
+            }
+            (sim, ef::MILESTONES.next_cost(start as f64 / 32.))
+        },
+        |(simbase, next_milestone_cost), end| {
+            simbase.goal = (end as f64 / 32.).min(*next_milestone_cost) - coast_offset;
+            simbase.simulate();
 
-        pub_data.insert(
-            start,
-            PubData {
-                next: best_next,
-                t: best_t,
-            },
-        );
-    }
+            let mut sim = simbase.fork();
+            sim.efdata.do_coasting = true;
+            sim.efdata.do_swap_search = swap_search;
+            sim.goal = end as f64 / 32.;
+            (sim, *next_milestone_cost)
+        },
+        |(mut sim, _)| sim.simulate().t,
+    )
+}
 
-    //pub_data.insert(23999, PubData { next: 24000, t: 0.1 });
+fn sl_pub_tables() -> Result<(), Box<dyn Error>> {
+    const WINDOW: [u32; 2] = [8, 96];
 
-    let updated_content = serde_json::to_string_pretty(&pub_data)?;
-    fs::write(path, updated_content)?;
+    pub_tables(
+        PubTableRun {
+            path: Path::new("data/sl.json"),
+            meta: PubMeta::new("sl", 16, 0, None, 3., WINDOW),
+            rows: (300 * 16)..(600 * 16),
+            ctend: 600 * 16,
+        },
+        |_| WINDOW,
+        |start| {
+            let mut sim = sl::SL::new(
+                TheoryData {
+                    tau: start as f64 * 0.5 / 16.,
+                    students: 0,
+                    rho: 0.,
+                },
+                0.,
+                None,
+            );
+            sim.sldata.do_coasting = false;
+            sim
+        },
+        |simbase, end| {
+            simbase.goal = end as f64 / 16. - 3.;
+            simbase.simulate();
 
-    Ok(())
+            let mut sim = simbase.fork();
+            sim.sldata.do_coasting = true;
+            sim.goal = end as f64 / 16.;
+            sim
+        },
+        |mut sim| sim.simulate().t,
+    )
 }
 
-fn ef_pub_tables() -> Result<(), Box<dyn Error>> {
-    let path = Path::new("data/ef.json");
-
-    const CTEND: u32 = 375 * 32;
-    const POINTS: [f64; 14] = [
-        10., 20., 30., 40., 50., 70., 90., 110., 130., 150., 250., 275., 300., 325.,
-    ];
+fn bap_pub_tables() -> Result<(), Box<dyn Error>> {
+    const WINDOW: [u32; 2] = [8, 80];
 
-    let content = fs::read_to_string(path)?;
+    pub_tables(
+        PubTableRun {
+            path: Path::new("data/bap.json"),
+            meta: PubMeta::new("bap", 16, 0, None, 3., WINDOW),
+            rows: (300 * 16)..(600 * 16),
+            ctend: 600 * 16,
+        },
+        |_| WINDOW,
+        |start| {
+            let mut sim = bap::BaP::new(
+                TheoryData {
+                    tau: start as f64 * 0.4 / 16.,
+                    students: 0,
+                    rho: 0.,
+                },
+                0.,
+                None,
+            );
+            sim.bapdata.do_coasting = false;
+            sim
+        },
+        |simbase, end| {
+            simbase.goal = end as f64 / 16. - 3.;
+            simbase.simulate();
 
-    let mut pub_data: HashMap<u32, PubData> = serde_json::from_str(&content)?;
+            let mut sim = simbase.fork();
+            sim.bapdata.do_coasting = true;
+            sim.goal = end as f64 / 16.;
+            sim
+        },
+        |mut sim| sim.simulate().t,
+    )
+}
 
-    println!("Read the file successfully.");
-    //println!("{:?}", pub_data.get(&24000));
+fn mf_pub_tables() -> Result<(), Box<dyn Error>> {
+    const WINDOW: [u32; 2] = [8, 80];
 
-    let mut best_t: f64;
-    let mut best_next: u32;
-    let mut sim: ef::EF;
-    let mut simbase: ef::EF;
-    let mut simt: f64;
-    let mut a: u32;
-    let mut b: u32;
-    let mut end_t: f64;
-    let mut next_milestone_cost: f64 = f64::MAX;
+    pub_tables(
+        PubTableRun {
+            path: Path::new("data/mf.json"),
+            meta: PubMeta::new("mf", 16, 0, None, 4., WINDOW),
+            rows: (300 * 16)..(600 * 16),
+            ctend: 600 * 16,
+        },
+        |_| WINDOW,
+        |start| {
+            let mut sim = mf::MF::new(
+                TheoryData {
+                    tau: start as f64 * 0.5 / 16.,
+                    students: 0,
+                    rho: 0.,
+                },
+                0.,
+                None,
+            );
+            sim.mfdata.do_coasting = false;
+            sim.mfdata.do_reset_search = false;
+            sim
+        },
+        |simbase, end| {
+            simbase.goal = end as f64 / 16. - 4.;
+            simbase.simulate();
 
-    for start in (0..(5 * 32)).rev() {
-        println!("Starting pub tables for {}", start as f64 / 32.);
+            let mut sim = simbase.fork();
+            sim.mfdata.do_coasting = true;
+            sim.mfdata.do_reset_search = true;
+            sim.goal = end as f64 / 16.;
+            sim
+        },
+        |mut sim| sim.simulate().t,
+    )
+}
 
-        for point in POINTS.iter() {
-            if start as f64 / 32. < *point {
-                next_milestone_cost = *point;
-                break;
+fn rz_pub_tables(black_hole: bool) -> Result<(), Box<dyn Error>> {
+    const WINDOW: [u32; 2] = [8, 80];
+
+    let strat = if black_hole { Some("blackhole") } else { None };
+
+    pub_tables(
+        PubTableRun {
+            path: Path::new(if black_hole {
+                "data/rzbh.json"
+            } else {
+                "data/rz.json"
+            }),
+            meta: PubMeta::new("rz", 16, 0, strat, 1.8, WINDOW),
+            rows: (300 * 16)..(600 * 16),
+            ctend: 600 * 16,
+        },
+        |_| WINDOW,
+        |start| {
+            let mut sim = rz::RZ::new(
+                TheoryData {
+                    tau: start as f64 * 0.4 / 16.,
+                    students: 0,
+                    rho: 0.,
+                },
+                0.,
+                None,
+            );
+            sim.rzdata.do_coasting = false;
+            if !black_hole {
+                sim.rzdata.derive_milestones = false;
+                sim.rzdata.milestones = [2, 0];
             }
-        }
-        best_t = f64::MAX;
-        best_next = 0;
-        a = if start >= 15 * 32 {
-            8.min(CTEND - start)
-        } else {
-            1i32.max(10 * 32 - start as i32) as u32
-        };
-        b = if start >= 15 * 32 {
-            175.min(CTEND - start)
-        } else {
-            13 * 32 - start
-        };
-
-        simbase = ef::EF::new(
-            TheoryData {
-                tau: start as f64 * 1.6 / 32.,
-                students: 0,
-                rho: 0.,
-            },
-            (start + a) as f64 / 32. - 2.,
-            None,
-        );
-        simbase.efdata.do_coasting = false;
-
-        for end in (start + a)..=(start + b) {
-            print!("\rTesting sim {}/{}", end - (start + a) + 1, b - a + 1);
-            std::io::Write::flush(&mut std::io::stdout())?;
-
-            simbase.goal = (end as f64 / 32.).min(next_milestone_cost) - 2.;
+            sim
+        },
+        |simbase, end| {
+            simbase.goal = end as f64 / 16. - 1.8;
             simbase.simulate();
 
-            sim = simbase.fork();
-            sim.efdata.do_coasting = true;
-            sim.goal = end as f64 / 32.;
-            simt = sim.simulate().t;
-
-            end_t = match pub_data.get(&end) {
-                None => 1e100,
-                Some(pdata) => pdata.t,
-            };
+            let mut sim = simbase.fork();
+            sim.rzdata.do_coasting = true;
+            sim.goal = end as f64 / 16.;
+            sim
+        },
+        |mut sim| sim.simulate().t,
+    )
+}
 
-            if simt + end_t < best_t {
-                best_t = simt + end_t;
-                best_next = end;
-            }
-        }
+fn fi_pub_tables() -> Result<(), Box<dyn Error>> {
+    const WINDOW: [u32; 2] = [8, 64];
 
-        println!();
-        println!(
-            "Best next: {} ; Total time remaining: {} ; Index diff: {}",
-            best_next as f64 / 32.,
-            get_time_string(best_t),
-            best_next - start
-        );
+    pub_tables(
+        PubTableRun {
+            path: Path::new("data/fi.json"),
+            meta: PubMeta::new("fi", 16, 0, None, 4., WINDOW),
+            rows: (200 * 16)..(500 * 16),
+            ctend: 500 * 16,
+        },
+        |_| WINDOW,
+        |start| {
+            // the prefix keeps the early allocation, swaps are searched in each fork
+            let mut sim = fi::FI::new(
+                TheoryData {
+                    tau: start as f64 * 0.4 / 16.,
+                    students: 0,
+                    rho: 0.,
+                },
+                0.,
+                None,
+            );
+            sim.fidata.do_coasting = false;
+            sim.fidata.do_swap_search = false;
+            sim.fidata.swap_dist = f64::MIN;
+            sim
+        },
+        |simbase, end| {
+            simbase.goal = end as f64 / 16. - 4.;
+            simbase.simulate();
 
-        pub_data.insert(
-            start,
-            PubData {
-                next: best_next,
-                t: best_t,
-            },
-        );
-    }
+            let mut sim = simbase.fork();
+            sim.fidata.do_coasting = true;
+            sim.fidata.do_swap_search = true;
+            sim.goal = end as f64 / 16.;
+            sim
+        },
+        |mut sim| sim.simulate().t,
+    )
+}
 
-    //pub_data.insert(23999, PubData { next: 24000, t: 0.1 });
+fn migrate_data_pub_tables() -> Result<(), Box<dyn Error>> {
+    let unknown = |theory: &str, grid: u32, students: u32| PubMeta {
+        version: PUB_TABLE_VERSION,
+        theory: theory.to_string(),
+        grid,
+        students,
+        strat: None,
+        coast_offset: None,
+        window: None,
+        params: None,
+        checkpoint: None,
+    };
 
-    let updated_content = serde_json::to_string_pretty(&pub_data)?;
-    fs::write(path, updated_content)?;
+    migrate_pub_table(
+        Path::new("data/t1c34.json"),
+        PubMeta::new("t1", 32, 500, Some("c34"), 6., [40, 150]),
+    )?;
+    migrate_pub_table(Path::new("data/t1.json"), unknown("t1", 32, 500))?;
+    migrate_pub_table(
+        Path::new("data/csr2.json"),
+        PubMeta::new("csr2", 16, 0, Some("active"), 1.8, [8, 80]),
+    )?;
+    migrate_pub_table(Path::new("data/csr2.json.old"), unknown("csr2", 16, 0))?;
 
     Ok(())
 }
 
-fn pub_tables_read_chain(path: String, rho: f64, grid: f64) -> Result<(), Box<dyn Error>> {
+fn pub_tables_read_chain(path: String, rho: f64, grid: u32) -> Result<(), Box<dyn Error>> {
     let path = Path::new(&path);
-    let content = fs::read_to_string(path)?;
-    let pub_table: HashMap<u32, PubData> = serde_json::from_str(&content)?;
+    let pub_table = PubTable::read_grid(path, grid)?.entries;
+    let grid = grid as f64;
     let mut index: u32 = (rho * grid).round() as u32;
     let mut curpubtime: f64;
 
@@ -789,6 +1088,12 @@ fn rust_sim_cli() -> Result<(), Box<dyn Error>> {
     let mode_input = &input("Enter the mode : ")?[..];
 
     match mode_input {
+        "T1" => {
+            let mut sim = sim_t1_input()?;
+            let res: SimRes = sim.simulate();
+            println!("{:?}", res.var_buys);
+            println!("{}", get_time_string(res.t));
+        }
         "T2" => {
             let mut sim = sim_t2_input()?;
             let res: SimRes = sim.simulate();
@@ -815,6 +1120,52 @@ fn rust_sim_cli() -> Result<(), Box<dyn Error>> {
             println!("{:?}", res.var_buys);
             println!("{}", get_time_string(res.t));
         }
+        "T7" => {
+            let mut sim = sim_t7_input()?;
+            let res: SimRes = sim.simulate();
+            println!("{:?}", res.var_buys);
+            println!("{}", get_time_string(res.t));
+        }
+        "CSR2" => {
+            let mut sim = sim_csr2_input()?;
+            let res: SimRes = sim.simulate();
+            println!("{:?}", res.var_buys);
+            println!("{}", get_time_string(res.t));
+        }
+        "FP" => {
+            let mut sim = sim_fp_input()?;
+            let res: SimRes = sim.simulate();
+            println!("{:?}", res.var_buys);
+            println!("{}", get_time_string(res.t));
+        }
+        "DE" => {
+            let mut sim = sim_de_input()?;
+            let res: SimRes = sim.simulate();
+            println!("{:?}", res.var_buys);
+            println!("{}", get_time_string(res.t));
+        }
+        "EF" => {
+            let mut sim = sim_ef();
+            sim.efdata.do_swap_search = true;
+            let res: SimRes = sim.simulate();
+            println!("{:?}", res.var_buys);
+
+            let defvec: Vec<VarBuy> = Vec::new();
+            let swaps: Vec<String> = res
+                .var_buys
+                .as_ref()
+                .unwrap_or(&defvec)
+                .iter()
+                .filter(|varbuy| varbuy.symb == "swap")
+                .map(|varbuy| get_time_string(varbuy.t))
+                .collect();
+            if swaps.is_empty() {
+                println!("Milestone swap: none");
+            } else {
+                println!("Milestone swap: {}", swaps.join(", "));
+            }
+            println!("{}", get_time_string(res.t));
+        }
         _ => (),
     }
 
@@ -823,17 +1174,31 @@ fn rust_sim_cli() -> Result<(), Box<dyn Error>> {
 
 fn main() -> Result<(), Box<dyn Error>> {
     //rust_sim_cli()?;
+    //migrate_data_pub_tables()?;
     //sim_t7();
-    let _res = t1_pub_tables()?;
+    let _res = t1_pub_tables(t1::T1strat::C34)?;
+    //let _ = t7_pub_tables(t7::T7strat::Ratio);
+    //let _ = ef_pub_tables(ef::EFstrat::Active, ef::EFstrat::Active.thresholds(), false);
+    //let _ = ef_pub_tables(ef::EFstrat::Active, ef::EFstrat::Active.thresholds(), true);
+    //let _ = csr2_pub_tables(csr2::CSR2strat::Swap);
+    //let _ = sl_pub_tables();
+    //let _ = bap_pub_tables();
+    //let _ = mf_pub_tables();
+    //let _ = rz_pub_tables(false);
+    //let _ = fi_pub_tables();
+    //let _ = de_pub_tables(None);
+    //t2_compare_strats(500, (250..=450).step_by(25), 10.);
     //let _res2 = get_pub_tables_range(s!("data/ef.json"), 375*32);
-    //let _ = get_pub_tables_diff(s!("data/de.json"), 875*16, 900*16, 16.);
-    //let _ = pub_tables_read_chain(s!("data/de1050.json"), 900., 16.);
-    //let _ = pub_tables_read_chain(s!("data/de1050.json"), (strtolog10(s!("4.97e143")) + 4f64.log10()) * 2.5 * 2.5, 16.);
-    let _ = pub_tables_read_chain(s!("data/t1c34.json"), 800., 32.);
+    //let _ = get_pub_tables_diff(s!("data/de.json"), 875*16, 900*16, 16);
+    //let _ = pub_tables_read_chain(s!("data/de1050.json"), 900., 16);
+    //let _ = pub_tables_read_chain(s!("data/de1050.json"), (strtolog10(s!("4.97e143")) + 4f64.log10()) * 2.5 * 2.5, 16);
+    let _ = pub_tables_read_chain(s!("data/t1c34.json"), 800., 32);
 
     //println!("{}", get_t(2291));
 
     //compress_pub_tables(s!("data/ef.json"), s!("data/EFpubtable.json"))?;
+    //pub_table_json_to_bin(Path::new("data/csr2.json"), Path::new("data/csr2.bin"))?;
+    //pub_table_bin_to_json(Path::new("data/csr2.bin"), Path::new("data/csr2.json"))?;
 
     Ok(())
 }
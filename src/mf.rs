@@ -0,0 +1,473 @@
+use crate::s;
+use crate::utils::*;
+
+/// Milestone tree: c1 exponent and a2 unlock.
+pub const MILESTONES: Milestones<2> = Milestones {
+    points: &[20., 50., 175.],
+    max_levels: [2, 1],
+    order: [0, 1],
+};
+
+/// Increment of the reset threshold between two reset forks, in log10.
+const RESET_STEP: f64 = 0.25;
+
+struct MFvars {
+    c1: Variable<FirstFreeCost<ExponentialCost>, StepwiseValue>,
+    c2: Variable<ExponentialCost, ExponentialValue>,
+    a1: Variable<ExponentialCost, StepwiseValue>,
+    a2: Variable<ExponentialCost, ExponentialValue>,
+    v1: Variable<ExponentialCost, StepwiseValue>,
+    v2: Variable<ExponentialCost, ExponentialValue>,
+}
+
+impl MFvars {
+    fn init() -> Self {
+        MFvars {
+            c1: Variable::new(
+                FirstFreeCost {
+                    model: ExponentialCost::new(10., 2.),
+                },
+                StepwiseValue::new(2., 10),
+            ),
+            c2: Variable::new(ExponentialCost::new(1e3, 50.), ExponentialValue::new(2.)),
+            a1: Variable::new(ExponentialCost::new(1e5, 25.), StepwiseValue::new(2., 4)),
+            a2: Variable::new(ExponentialCost::new(1e8, 100.), ExponentialValue::new(1.25)),
+            v1: Variable::new(
+                ExponentialCost::new(80., 10.),
+                StepwiseValue::new_offset(2., 10, 1.),
+            ),
+            v2: Variable::new(
+                ExponentialCost::new(1e4, 10f64.powf(1.5)),
+                ExponentialValue::new(2.),
+            ),
+        }
+    }
+
+    fn getm(&mut self, id: usize) -> &mut dyn VariableTrait {
+        match id {
+            0 => &mut self.c1,
+            1 => &mut self.c2,
+            2 => &mut self.a1,
+            3 => &mut self.a2,
+            4 => &mut self.v1,
+            _ => &mut self.v2,
+        }
+    }
+
+    fn get(&self, id: usize) -> &dyn VariableTrait {
+        match id {
+            0 => &self.c1,
+            1 => &self.c2,
+            2 => &self.a1,
+            3 => &self.a2,
+            4 => &self.v1,
+            _ => &self.v2,
+        }
+    }
+
+    fn set(&mut self, lvls: [u32; 6]) {
+        for (i, level) in lvls.iter().enumerate() {
+            self.getm(i).set(*level);
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct MFdata {
+    caps: [u32; 6],
+    /// Milestone levels, in `MILESTONES` order.
+    pub milestones: [u32; 2],
+    /// Derive the milestones from rho and tau instead of keeping the given ones.
+    pub derive_milestones: bool,
+    pub do_coasting: bool,
+    /// Explore several reset timings near the goal instead of only resetting greedily.
+    pub do_reset_search: bool,
+    /// Velocity gain (log10) that triggers a reset.
+    pub reset_ratio: f64,
+    /// Largest velocity gain (log10) the reset search waits for.
+    pub reset_ratio_max: f64,
+    /// Distance to the goal (log10) under which resets are searched.
+    pub reset_search_window: f64,
+    reset_threshold: f64,
+    resets: u32,
+}
+
+pub struct MFstate {
+    pub levels: [u32; 6],
+    pub x: f64,
+    pub i: f64,
+    pub v: f64,
+}
+
+pub struct MF {
+    data: TheoryData,
+    pub mfdata: MFdata,
+    pub goal: f64,
+    rho: f64,
+    maxrho: f64,
+    multiplier: f64,
+    x: f64,
+    i: f64,
+    v: f64,
+    vars: MFvars,
+    varbuys: Vec<VarBuy>,
+
+    t: f64,
+    dt: f64,
+    ddt: f64,
+    depth: u32,
+
+    best_res: SimRes,
+}
+
+impl MF {
+    pub fn new(data: TheoryData, goal: f64, state: Option<MFstate>) -> Self {
+        let mut mf: MF = MF {
+            data,
+            mfdata: MFdata {
+                caps: [u32::MAX; 6],
+                milestones: MILESTONES.max_levels,
+                derive_milestones: true,
+                do_coasting: true,
+                do_reset_search: true,
+                reset_ratio: 3f64.log10(),
+                reset_ratio_max: 30f64.log10(),
+                reset_search_window: 4.,
+                reset_threshold: 3f64.log10(),
+                resets: 0,
+            },
+            goal,
+            rho: 0.,
+            maxrho: 0.,
+            multiplier: 0.,
+            x: f64::NEG_INFINITY,
+            i: f64::NEG_INFINITY,
+            v: 0.,
+            vars: MFvars::init(),
+            varbuys: Vec::new(),
+
+            t: 0.,
+            dt: 1.5,
+            ddt: 1.0001,
+            depth: 0,
+
+            best_res: SimRes::default(),
+        };
+
+        match state {
+            None => mf.v = mf.get_target_velocity(),
+            Some(state) => {
+                mf.vars.set(state.levels);
+                mf.x = state.x;
+                mf.i = state.i;
+                mf.v = state.v;
+            }
+        }
+
+        mf.rho = mf.data.rho;
+        mf.multiplier = mf.get_multiplier(mf.data.tau);
+
+        mf
+    }
+
+    pub fn fork(&self) -> Self {
+        let mut new: MF = MF {
+            data: self.data,
+            mfdata: self.mfdata,
+            goal: self.goal,
+            rho: self.rho,
+            maxrho: self.maxrho,
+            multiplier: self.multiplier,
+            x: self.x,
+            i: self.i,
+            v: self.v,
+            vars: MFvars::init(),
+            varbuys: self.varbuys.clone(),
+            t: self.t,
+            dt: self.dt,
+            ddt: self.ddt,
+            depth: self.depth + 1,
+
+            best_res: SimRes::default(),
+        };
+
+        for i in 0..6 {
+            new.vars.getm(i).set(self.vars.get(i).get_level())
+        }
+
+        new
+    }
+
+    /// Milestones reached with the max of rho and the last pub's rho.
+    fn update_milestones(&mut self) {
+        if self.mfdata.derive_milestones {
+            let rho = self.maxrho.max(self.data.tau * 2.);
+            self.mfdata.milestones = MILESTONES.at(rho);
+        }
+    }
+
+    fn get_multiplier(&self, tau: f64) -> f64 {
+        tau * 0.76 - 10.
+    }
+
+    /// Velocity the particle would get from a reset with the current v levels.
+    fn get_target_velocity(&self) -> f64 {
+        self.vars.v1.value + self.vars.v2.value
+    }
+
+    fn get_variable_conditions(&self, id: usize) -> bool {
+        match id {
+            0..=2 | 4 | 5 => true,
+            3 => self.mfdata.milestones[1] >= 1,
+            _ => false,
+        }
+    }
+
+    fn eval_coast_one(&self, dist: f64, lbound: f64, ubound: f64) -> BuyEval {
+        if dist > ubound {
+            BuyEval::BUY
+        } else if dist > lbound {
+            BuyEval::FORK
+        } else {
+            BuyEval::SKIP
+        }
+    }
+
+    fn eval_coast(&self, id: usize, cost: f64) -> BuyEval {
+        let dist: f64 = self.goal - cost;
+        if dist > 3. || !self.mfdata.do_coasting {
+            return BuyEval::BUY;
+        }
+        match id {
+            0 => self.eval_coast_one(dist, 0.4, 1.2),
+            1 => self.eval_coast_one(dist, 0.2, 0.8),
+            2 | 3 => self.eval_coast_one(dist, 0.4, 1.2),
+            // v levels only matter after the next reset
+            4 | 5 => self.eval_coast_one(dist, 1., 2.),
+            _ => BuyEval::SKIP,
+        }
+    }
+
+    fn eval_ratio(&self, id: usize) -> BuyEval {
+        if match id {
+            0 => {
+                self.vars.c1.cost + (7. + (self.vars.c1.level % 10) as f64).log10()
+                    < self.vars.c2.cost
+            }
+            1 => true,
+            2 => {
+                self.vars.a1.cost + (2. + (self.vars.a1.level % 4) as f64).log10()
+                    < self.vars.c2.cost.min(if self.get_variable_conditions(3) {
+                        self.vars.a2.cost
+                    } else {
+                        f64::MAX
+                    })
+            }
+            3 => self.vars.a2.cost + 2f64.log10() < self.vars.c2.cost,
+            4 => {
+                self.vars.v1.cost + (5. + (self.vars.v1.level % 10) as f64).log10()
+                    < self.vars.v2.cost
+            }
+            5 => self.vars.v2.cost + 2f64.log10() < self.vars.c2.cost,
+            _ => false,
+        } {
+            BuyEval::BUY
+        } else {
+            BuyEval::SKIP
+        }
+    }
+
+    /// Resetting trades the accumulated x and i for the velocity bought since
+    /// the last reset. Near the goal, each threshold between `reset_ratio` and
+    /// `reset_ratio_max` is tried in a fork.
+    fn eval_reset(&self) -> BuyEval {
+        let gain: f64 = self.get_target_velocity() - self.v;
+        if gain < self.mfdata.reset_threshold {
+            return BuyEval::SKIP;
+        }
+        if !self.mfdata.do_reset_search || self.goal - self.maxrho > self.mfdata.reset_search_window
+        {
+            return BuyEval::BUY;
+        }
+        if self.mfdata.reset_threshold + RESET_STEP > self.mfdata.reset_ratio_max {
+            BuyEval::BUY
+        } else {
+            BuyEval::FORK
+        }
+    }
+
+    fn reset(&mut self) {
+        self.x = f64::NEG_INFINITY;
+        self.i = f64::NEG_INFINITY;
+        self.v = self.get_target_velocity();
+        self.mfdata.reset_threshold = self.mfdata.reset_ratio;
+        self.mfdata.resets += 1;
+
+        if self.maxrho > self.data.tau * 2. - 5. {
+            self.varbuys.push(VarBuy {
+                symb: s!("reset"),
+                lvl: self.mfdata.resets,
+                t: self.t,
+            })
+        }
+    }
+
+    fn check_reset(&mut self) {
+        let reset_eval: BuyEval = self.eval_reset();
+        if reset_eval == BuyEval::SKIP {
+            return;
+        }
+        if reset_eval == BuyEval::FORK {
+            let mut fork: MF = self.fork();
+            fork.mfdata.reset_threshold = self.get_target_velocity() - self.v + RESET_STEP;
+            let res: SimRes = fork.simulate();
+            if res.t < self.best_res.t {
+                self.best_res.t = res.t;
+                self.best_res.var_buys = res.var_buys;
+            }
+        }
+        self.reset();
+    }
+
+    fn tick(&mut self) {
+        let logdt: f64 = self.dt.log10();
+
+        self.x = log10add(self.x, self.v + logdt);
+        self.i = log10add(self.i, self.vars.a1.value + self.vars.a2.value + logdt);
+
+        let rhodot = self.vars.c1.value * (1. + 0.05 * self.mfdata.milestones[0] as f64)
+            + self.vars.c2.value
+            + self.x
+            + self.i;
+        self.rho = log10add(self.rho, rhodot + self.multiplier + logdt);
+        self.maxrho = self.maxrho.max(self.rho);
+
+        self.t += self.dt / 1.5;
+        self.dt *= self.ddt;
+    }
+
+    pub fn simulate(&mut self) -> SimRes {
+        while self.maxrho < self.goal {
+            self.update_milestones();
+            self.tick();
+            self.buy();
+            self.check_reset();
+        }
+
+        if self.t < self.best_res.t {
+            SimRes {
+                t: self.t,
+                var_buys: Some(self.varbuys.clone()),
+            }
+        } else {
+            SimRes {
+                t: self.best_res.t,
+                var_buys: self.best_res.var_buys.clone(),
+            }
+        }
+    }
+
+    fn buy(&mut self) {
+        let mut cost: f64;
+        let mut coast_eval: BuyEval;
+        let mut ratio_eval: BuyEval;
+        let names = ["c1", "c2", "a1", "a2", "v1", "v2"];
+
+        for id in (0..6).rev() {
+            if self.vars.get(id).get_level() >= self.mfdata.caps[id]
+                || !self.get_variable_conditions(id)
+            {
+                continue;
+            }
+
+            cost = self.vars.get(id).get_cost();
+
+            while self.rho > cost {
+                coast_eval = self.eval_coast(id, cost);
+                ratio_eval = self.eval_ratio(id);
+
+                if coast_eval != BuyEval::SKIP {
+                    if ratio_eval == BuyEval::SKIP {
+                        break;
+                    }
+                    if coast_eval == BuyEval::FORK {
+                        let mut fork: MF = self.fork();
+                        let lvl: u32 = self.vars.get(id).get_level();
+                        fork.mfdata.caps[id] = lvl;
+                        let res: SimRes = fork.simulate();
+                        if res.t < self.best_res.t {
+                            self.best_res.t = res.t;
+                            self.best_res.var_buys = res.var_buys;
+                        }
+                    }
+
+                    self.rho = log10sub(self.rho, cost);
+                    self.vars.getm(id).buy();
+                    cost = self.vars.get(id).get_cost();
+
+                    if self.maxrho > self.data.tau * 2. - 5. {
+                        self.varbuys.push(VarBuy {
+                            symb: s!(names[id]),
+                            lvl: self.vars.get(id).get_level(),
+                            t: self.t,
+                        })
+                    }
+                } else {
+                    self.mfdata.caps[id] = self.vars.get(id).get_level();
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fresh_mf(levels: [u32; 6]) -> MF {
+        let mut mf = MF::new(
+            TheoryData {
+                tau: 50.,
+                students: 0,
+                rho: 0.,
+            },
+            200.,
+            None,
+        );
+        mf.vars.set(levels);
+        mf.v = mf.get_target_velocity();
+        mf
+    }
+
+    #[test]
+    fn tick_starts_from_empty_x_and_i() {
+        // a1 at level 0 adds nothing to i, which must stay empty instead of NaN
+        let mut mf = fresh_mf([1, 0, 0, 0, 1, 0]);
+        let logdt: f64 = mf.dt.log10();
+        mf.tick();
+        assert_eq!(mf.x, mf.v + logdt);
+        assert_eq!(mf.i, f64::NEG_INFINITY);
+        assert!(mf.rho.is_finite());
+    }
+
+    #[test]
+    fn reset_empties_x_and_i() {
+        let mut mf = fresh_mf([1, 0, 1, 0, 1, 0]);
+        for _ in 0..100 {
+            mf.tick();
+        }
+        let rho = mf.rho;
+        mf.vars.set([1, 0, 1, 0, 5, 0]);
+        mf.reset();
+        assert_eq!(mf.x, f64::NEG_INFINITY);
+        assert_eq!(mf.i, f64::NEG_INFINITY);
+        assert_eq!(mf.v, mf.get_target_velocity());
+
+        let logdt: f64 = mf.dt.log10();
+        mf.tick();
+        assert_eq!(mf.x, mf.v + logdt);
+        assert_eq!(mf.i, mf.vars.a1.value + logdt);
+        assert!(mf.rho.is_finite() && mf.rho >= rho);
+    }
+}
@@ -0,0 +1,603 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+use signal_hook::consts::SIGINT;
+
+/// Schema version written in the header. Bump it on any incompatible change.
+pub const PUB_TABLE_VERSION: u32 = 1;
+
+/// Least time between two checkpoints of a table being generated.
+const CHECKPOINT_PERIOD: Duration = Duration::from_secs(60);
+
+/// Binary tables: magic, schema version, header length, JSON header, first index,
+/// entry count, then one record per grid index from the first one on. All little-endian.
+const BIN_MAGIC: &[u8; 4] = b"PUBT";
+/// A record is `next` (u32) then `t` (f64).
+const BIN_RECORD_LEN: usize = 12;
+/// `next` of the grid indices the table has no entry for.
+const BIN_NO_ENTRY: u32 = u32::MAX;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PubData {
+    pub next: u32,
+    pub t: f64,
+}
+
+/// Header of a pub table file: what the table was generated for.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PubMeta {
+    pub version: u32,
+    pub theory: String,
+    /// Entries per rho (log10) unit; keys are rho * grid.
+    pub grid: u32,
+    pub students: u32,
+    pub strat: Option<String>,
+    /// How far (log10) below the next pub the non-coasting base sim stops.
+    pub coast_offset: Option<f64>,
+    /// Range of the next pub searched from each start, in grid steps.
+    /// `None` when the range depends on the start.
+    pub window: Option<[u32; 2]>,
+    /// Strategy parameters beyond `strat`, for theories whose strategies take any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub params: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checkpoint: Option<Checkpoint>,
+}
+
+/// Progress of an unfinished generation run, which goes from the top row down.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Start and end of the rows of the run.
+    pub rows: [u32; 2],
+    /// Lowest row completed.
+    pub done: u32,
+}
+
+impl PubMeta {
+    pub fn new(
+        theory: &str,
+        grid: u32,
+        students: u32,
+        strat: Option<&str>,
+        coast_offset: f64,
+        window: [u32; 2],
+    ) -> Self {
+        PubMeta {
+            version: PUB_TABLE_VERSION,
+            theory: theory.to_string(),
+            grid,
+            students,
+            strat: strat.map(str::to_string),
+            coast_offset: Some(coast_offset),
+            window: Some(window),
+            params: None,
+            checkpoint: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PubTable {
+    pub meta: PubMeta,
+    pub entries: BTreeMap<u32, PubData>,
+    #[serde(skip)]
+    last_save: Option<Instant>,
+}
+
+fn invalid_data(msg: String) -> Box<dyn Error> {
+    Box::new(io::Error::new(io::ErrorKind::InvalidData, msg))
+}
+
+/// Writes to a temporary file next to `path` then renames it over `path`,
+/// so an interruption never leaves a partly written table.
+fn write_atomic(path: &Path, bytes: &[u8]) -> Result<(), Box<dyn Error>> {
+    let tmp = format!("{}.tmp", path.display());
+    fs::write(&tmp, bytes)?;
+    fs::rename(&tmp, path)?;
+
+    Ok(())
+}
+
+/// Set by Ctrl-C once a table is opened for generation. A second Ctrl-C exits right away.
+fn interrupt_flag() -> &'static Arc<AtomicBool> {
+    static FLAG: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+    FLAG.get_or_init(|| {
+        let flag = Arc::new(AtomicBool::new(false));
+        // registering only fails for signals that cannot be caught, which SIGINT is not
+        let _ = signal_hook::flag::register_conditional_shutdown(SIGINT, 1, Arc::clone(&flag));
+        let _ = signal_hook::flag::register(SIGINT, Arc::clone(&flag));
+        flag
+    })
+}
+
+impl PubTable {
+    /// Reads a table, rejecting other schema versions and bare legacy maps.
+    pub fn read(path: &Path) -> Result<PubTable, Box<dyn Error>> {
+        let content = fs::read_to_string(path)?;
+        let value: serde_json::Value = serde_json::from_str(&content)?;
+
+        let version = match value.get("meta").and_then(|meta| meta.get("version")) {
+            None => {
+                return Err(invalid_data(format!(
+                    "{} has no header, migrate it with migrate_pub_table",
+                    path.display()
+                )));
+            }
+            Some(version) => version.as_u64(),
+        };
+        if version != Some(PUB_TABLE_VERSION as u64) {
+            return Err(invalid_data(format!(
+                "{} has schema version {:?}, expected {PUB_TABLE_VERSION}",
+                path.display(),
+                version
+            )));
+        }
+
+        Ok(serde_json::from_value(value)?)
+    }
+
+    /// Reads a table and checks that it was generated on `grid`.
+    pub fn read_grid(path: &Path, grid: u32) -> Result<PubTable, Box<dyn Error>> {
+        let table = PubTable::read(path)?;
+        if table.meta.grid != grid {
+            return Err(invalid_data(format!(
+                "{} is on a grid of {}, expected {grid}",
+                path.display(),
+                table.meta.grid
+            )));
+        }
+
+        Ok(table)
+    }
+
+    /// Opens a table to extend it with `meta`, or starts a new one if the file does not exist.
+    /// Everything in the header but the checkpoint must match, as rows generated with another
+    /// coast offset or window would not be comparable. Ctrl-C is caught from then on.
+    pub fn open(path: &Path, meta: PubMeta) -> Result<PubTable, Box<dyn Error>> {
+        interrupt_flag();
+        if !path.exists() {
+            return Ok(PubTable {
+                meta,
+                entries: BTreeMap::new(),
+                last_save: Some(Instant::now()),
+            });
+        }
+
+        let mut table = PubTable::read_grid(path, meta.grid)?;
+        let old = &table.meta;
+        if old.theory != meta.theory || old.students != meta.students || old.strat != meta.strat {
+            return Err(invalid_data(format!(
+                "{} was generated for {} ({} students, strategy {:?}), not {} ({} students, strategy {:?})",
+                path.display(),
+                old.theory,
+                old.students,
+                old.strat,
+                meta.theory,
+                meta.students,
+                meta.strat
+            )));
+        }
+        if old.params != meta.params {
+            return Err(invalid_data(format!(
+                "{} was generated with parameters {}, not {}",
+                path.display(),
+                old.params.clone().unwrap_or_default(),
+                meta.params.clone().unwrap_or_default()
+            )));
+        }
+        if old.coast_offset != meta.coast_offset || old.window != meta.window {
+            return Err(invalid_data(format!(
+                "{} was generated with coast offset {:?} and window {:?}, not {:?} and {:?}",
+                path.display(),
+                old.coast_offset,
+                old.window,
+                meta.coast_offset,
+                meta.window
+            )));
+        }
+        table.last_save = Some(Instant::now());
+
+        Ok(table)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        write_atomic(path, serde_json::to_string_pretty(self)?.as_bytes())
+    }
+
+    /// Rows of `rows` left to generate, from the top down. A run on the same rows
+    /// that did not finish resumes below the last row it completed.
+    pub fn rows_left(&self, rows: &Range<u32>) -> std::iter::Rev<Range<u32>> {
+        let end = match &self.meta.checkpoint {
+            Some(checkpoint) if checkpoint.rows == [rows.start, rows.end] => {
+                println!("Resuming rows {:?} below row {}", rows, checkpoint.done);
+                checkpoint.done
+            }
+            _ => rows.end,
+        };
+
+        (rows.start..end.max(rows.start)).rev()
+    }
+
+    /// Marks `row` of `rows` as completed. Writes a checkpoint every `CHECKPOINT_PERIOD`,
+    /// and after a Ctrl-C writes one and returns an `Interrupted` error. This is the only
+    /// place Ctrl-C is acted on, so a run stops once the row in progress is done.
+    pub fn complete_row(
+        &mut self,
+        path: &Path,
+        rows: &Range<u32>,
+        row: u32,
+    ) -> Result<(), Box<dyn Error>> {
+        self.meta.checkpoint = Some(Checkpoint {
+            rows: [rows.start, rows.end],
+            done: row,
+        });
+
+        let interrupted = interrupt_flag().load(Ordering::Relaxed);
+        if interrupted
+            || self
+                .last_save
+                .is_none_or(|t| t.elapsed() >= CHECKPOINT_PERIOD)
+        {
+            self.write(path)?;
+            self.last_save = Some(Instant::now());
+        }
+        if interrupted {
+            return Err(Box::new(io::Error::new(
+                io::ErrorKind::Interrupted,
+                format!("interrupted, {} is saved down to row {row}", path.display()),
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Clears the checkpoint and writes the finished table.
+    pub fn finish(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        self.meta.checkpoint = None;
+        self.write(path)
+    }
+
+    /// Writes the dense binary form, see `PubTableBin`.
+    pub fn write_bin(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let meta = serde_json::to_vec(&self.meta)?;
+        let first: u32 = self.entries.keys().next().copied().unwrap_or(0);
+        let len: u32 = self
+            .entries
+            .keys()
+            .next_back()
+            .map_or(0, |last| last - first + 1);
+
+        let mut bytes: Vec<u8> =
+            Vec::with_capacity(20 + meta.len() + len as usize * BIN_RECORD_LEN);
+        bytes.extend_from_slice(BIN_MAGIC);
+        bytes.extend_from_slice(&self.meta.version.to_le_bytes());
+        bytes.extend_from_slice(&(meta.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&meta);
+        bytes.extend_from_slice(&first.to_le_bytes());
+        bytes.extend_from_slice(&len.to_le_bytes());
+        for index in first..first + len {
+            let (next, t) = match self.entries.get(&index) {
+                None => (BIN_NO_ENTRY, 0.),
+                Some(data) => (data.next, data.t),
+            };
+            bytes.extend_from_slice(&next.to_le_bytes());
+            bytes.extend_from_slice(&t.to_le_bytes());
+        }
+        write_atomic(path, &bytes)
+    }
+
+    /// Reads every entry of a binary table.
+    pub fn read_bin(path: &Path) -> Result<PubTable, Box<dyn Error>> {
+        let bin = PubTableBin::open(path)?;
+        let entries = (bin.first..bin.first + bin.len)
+            .filter_map(|index| bin.get(index).map(|data| (index, data)))
+            .collect();
+
+        Ok(PubTable {
+            meta: bin.meta,
+            entries,
+            last_save: None,
+        })
+    }
+}
+
+/// Memory-mapped binary table. Entries are stored densely by grid index,
+/// so a lookup reads one record without parsing the rest of the file.
+pub struct PubTableBin {
+    pub meta: PubMeta,
+    map: Mmap,
+    data_offset: usize,
+    first: u32,
+    len: u32,
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+impl PubTableBin {
+    /// Maps a binary table, rejecting other schema versions.
+    pub fn open(path: &Path) -> Result<PubTableBin, Box<dyn Error>> {
+        let file = fs::File::open(path)?;
+        // SAFETY: pub tables are only written whole by `write_bin`, never while being read.
+        let map = unsafe { Mmap::map(&file)? };
+        let truncated = || invalid_data(format!("{} is truncated", path.display()));
+
+        if map.get(0..4) != Some(BIN_MAGIC) {
+            return Err(invalid_data(format!(
+                "{} is not a binary pub table",
+                path.display()
+            )));
+        }
+        let version = read_u32(&map, 4).ok_or_else(truncated)?;
+        if version != PUB_TABLE_VERSION {
+            return Err(invalid_data(format!(
+                "{} has schema version {version}, expected {PUB_TABLE_VERSION}",
+                path.display()
+            )));
+        }
+        let meta_len = read_u32(&map, 8).ok_or_else(truncated)? as usize;
+        let meta: PubMeta =
+            serde_json::from_slice(map.get(12..12 + meta_len).ok_or_else(truncated)?)?;
+        let first = read_u32(&map, 12 + meta_len).ok_or_else(truncated)?;
+        let len = read_u32(&map, 16 + meta_len).ok_or_else(truncated)?;
+        let data_offset = 20 + meta_len;
+        if map.len() < data_offset + len as usize * BIN_RECORD_LEN {
+            return Err(truncated());
+        }
+
+        Ok(PubTableBin {
+            meta,
+            map,
+            data_offset,
+            first,
+            len,
+        })
+    }
+
+    /// Maps a binary table and checks that it was generated on `grid`.
+    pub fn open_grid(path: &Path, grid: u32) -> Result<PubTableBin, Box<dyn Error>> {
+        let bin = PubTableBin::open(path)?;
+        if bin.meta.grid != grid {
+            return Err(invalid_data(format!(
+                "{} is on a grid of {}, expected {grid}",
+                path.display(),
+                bin.meta.grid
+            )));
+        }
+
+        Ok(bin)
+    }
+
+    pub fn get(&self, index: u32) -> Option<PubData> {
+        if index < self.first || index - self.first >= self.len {
+            return None;
+        }
+        let offset = self.data_offset + (index - self.first) as usize * BIN_RECORD_LEN;
+        let record = &self.map[offset..offset + BIN_RECORD_LEN];
+        let next = u32::from_le_bytes(record[0..4].try_into().ok()?);
+        if next == BIN_NO_ENTRY {
+            return None;
+        }
+
+        Some(PubData {
+            next,
+            t: f64::from_le_bytes(record[4..12].try_into().ok()?),
+        })
+    }
+}
+
+/// Rewrites a bare `{rho * grid: PubData}` map from before the header with the given header.
+pub fn migrate_pub_table(path: &Path, meta: PubMeta) -> Result<(), Box<dyn Error>> {
+    let content = fs::read_to_string(path)?;
+    let entries: BTreeMap<u32, PubData> = match serde_json::from_str(&content) {
+        Ok(entries) => entries,
+        Err(_) => {
+            return Err(invalid_data(format!(
+                "{} is not a bare pub table map",
+                path.display()
+            )));
+        }
+    };
+
+    PubTable {
+        meta,
+        entries,
+        last_save: None,
+    }
+    .write(path)
+}
+
+/// Converts a JSON table to the binary form.
+pub fn pub_table_json_to_bin(source: &Path, dest: &Path) -> Result<(), Box<dyn Error>> {
+    PubTable::read(source)?.write_bin(dest)
+}
+
+/// Converts a binary table back to JSON.
+pub fn pub_table_bin_to_json(source: &Path, dest: &Path) -> Result<(), Box<dyn Error>> {
+    PubTable::read_bin(source)?.write(dest)
+}
+
+/// Maps `items` with `f` on one thread per core, each taking the next item left when
+/// it is free. Results are in the order of `items`, whatever order they finish in.
+/// Ctrl-C does not stop the items left; pub table runs check it between rows.
+pub fn par_map<T: Send, R: Send>(items: Vec<T>, f: impl Fn(T) -> R + Sync) -> Vec<R> {
+    let len = items.len();
+    let threads = thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(len);
+    let queue = Mutex::new(items.into_iter().enumerate());
+    let results: Mutex<Vec<Option<R>>> = Mutex::new((0..len).map(|_| None).collect());
+
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| {
+                loop {
+                    let next = queue.lock().unwrap().next();
+                    let Some((i, item)) = next else {
+                        break;
+                    };
+                    let res = f(item);
+                    results.lock().unwrap()[i] = Some(res);
+                }
+            });
+        }
+    });
+
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|res| res.unwrap())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// A file in the temp dir, unique to this process and test.
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("pubtable_{}_{name}", std::process::id()))
+    }
+
+    fn meta() -> PubMeta {
+        PubMeta::new("csr2", 16, 0, Some("active"), 1.8, [8, 80])
+    }
+
+    /// Rows 100, 102 and 105, with gaps between them.
+    fn table() -> PubTable {
+        let entries = [(100, 105, 2.5), (102, 110, 1.25), (105, 105, 0.)]
+            .into_iter()
+            .map(|(index, next, t)| (index, PubData { next, t }))
+            .collect();
+        PubTable {
+            meta: meta(),
+            entries,
+            last_save: None,
+        }
+    }
+
+    fn assert_same_entries(a: &PubTable, b: &PubTable) {
+        let keys = |table: &PubTable| table.entries.keys().copied().collect::<Vec<u32>>();
+        assert_eq!(keys(a), keys(b));
+        for (index, data) in &a.entries {
+            assert_eq!(data.next, b.entries[index].next);
+            assert_eq!(data.t, b.entries[index].t);
+        }
+    }
+
+    #[test]
+    fn json_bin_round_trip_keeps_gaps() {
+        let json = temp_path("round_trip.json");
+        let bin = temp_path("round_trip.bin");
+        let back = temp_path("round_trip_back.json");
+        table().write(&json).unwrap();
+
+        pub_table_json_to_bin(&json, &bin).unwrap();
+        let mapped = PubTableBin::open_grid(&bin, 16).unwrap();
+        assert_eq!(mapped.meta, meta());
+        assert_eq!((mapped.first, mapped.len), (100, 6));
+        let offset = mapped.data_offset + BIN_RECORD_LEN;
+        assert_eq!(read_u32(&mapped.map, offset), Some(BIN_NO_ENTRY));
+        assert!(mapped.get(99).is_none());
+        assert!(mapped.get(101).is_none());
+        assert!(mapped.get(106).is_none());
+        let row = mapped.get(102).unwrap();
+        assert_eq!((row.next, row.t), (110, 1.25));
+
+        pub_table_bin_to_json(&bin, &back).unwrap();
+        let read = PubTable::read(&back).unwrap();
+        assert_eq!(read.meta, meta());
+        assert_same_entries(&read, &table());
+
+        for path in [json, bin, back] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn open_grid_rejects_bad_grid_truncation_and_magic() {
+        let bin = temp_path("bad.bin");
+        table().write_bin(&bin).unwrap();
+        let err = PubTableBin::open_grid(&bin, 32).err().unwrap();
+        assert!(err.to_string().contains("grid of 16"), "{err}");
+
+        let mut bytes = fs::read(&bin).unwrap();
+        fs::write(&bin, &bytes[..bytes.len() - 1]).unwrap();
+        let err = PubTableBin::open_grid(&bin, 16).err().unwrap();
+        assert!(err.to_string().contains("truncated"), "{err}");
+
+        bytes[0..4].copy_from_slice(b"JSON");
+        fs::write(&bin, &bytes).unwrap();
+        let err = PubTableBin::open_grid(&bin, 16).err().unwrap();
+        assert!(err.to_string().contains("not a binary pub table"), "{err}");
+
+        fs::remove_file(bin).unwrap();
+    }
+
+    #[test]
+    fn rows_left_resumes_below_the_last_checkpoint() {
+        let path = temp_path("resume.json");
+        let rows = 10..20;
+        let mut table = PubTable::open(&path, meta()).unwrap();
+        assert!(table.rows_left(&rows).eq((10..20).rev()));
+        for row in [19, 18] {
+            table.entries.insert(row, PubData { next: 20, t: 1. });
+            table.last_save = None;
+            table.complete_row(&path, &rows, row).unwrap();
+        }
+        table.entries.insert(17, PubData { next: 20, t: 1. });
+
+        let table = PubTable::open(&path, meta()).unwrap();
+        assert!(table.rows_left(&rows).eq((10..18).rev()));
+        assert!(table.rows_left(&(0..20)).eq((0..20).rev()));
+        assert_eq!(
+            table.entries.keys().copied().collect::<Vec<u32>>(),
+            [18, 19]
+        );
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn open_rejects_another_coast_offset_or_window() {
+        let path = temp_path("mismatch.json");
+        table().write(&path).unwrap();
+        assert!(PubTable::open(&path, meta()).is_ok());
+
+        let other_offset = PubMeta::new("csr2", 16, 0, Some("active"), 2., [8, 80]);
+        let err = PubTable::open(&path, other_offset).err().unwrap();
+        assert!(err.to_string().contains("coast offset"), "{err}");
+        let other_window = PubMeta::new("csr2", 16, 0, Some("active"), 1.8, [8, 96]);
+        assert!(PubTable::open(&path, other_window).is_err());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn par_map_keeps_item_order() {
+        assert!(par_map(Vec::<u32>::new(), |x| x).is_empty());
+
+        // later items finish first when run on several threads
+        let items: Vec<u64> = (0..32).collect();
+        let res = par_map(items.clone(), |x| {
+            thread::sleep(Duration::from_millis(32 - x));
+            x * x
+        });
+        assert_eq!(res, items.iter().map(|x| x * x).collect::<Vec<u64>>());
+    }
+}
@@ -0,0 +1,467 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::sync::OnceLock;
+
+use crate::s;
+use crate::utils::*;
+
+/// Milestone tree: c1 exponent and the black hole, see `RZdata::black_hole_t`.
+pub const MILESTONES: Milestones<2> = Milestones {
+    points: &[25., 50., 125.],
+    max_levels: [2, 1],
+    order: [0, 1],
+};
+
+/// Grid step and range of the tabulated Z(t). Past the end of the table, Z is evaluated directly
+/// and cached by t, since coasting forks replay the ticks of the sim they were forked from.
+const ZETA_STEP: f64 = 0.05;
+const ZETA_TABLE_END: f64 = 5e4;
+const ZETA_CACHE_MAX: usize = 1 << 20;
+
+thread_local! {
+    static ZETA_CACHE: RefCell<HashMap<u64, f64>> = RefCell::new(HashMap::new());
+}
+
+/// Riemann-Siegel Z function, |Z(t)| = |zeta(1/2 + it)|.
+/// Uses the main sum and the first correction term, which is plenty for the sim.
+fn riemann_siegel_z(t: f64) -> f64 {
+    let t = t.max(1.);
+    let theta = t / 2. * (t / (2. * PI)).ln() - t / 2. - PI / 8. + 1. / (48. * t)
+        + 7. / (5760. * t.powi(3));
+    let a = (t / (2. * PI)).sqrt();
+    let n = a.floor() as u32;
+    let p = a - n as f64;
+
+    let mut z = 0.;
+    for k in 1..=n {
+        let k = k as f64;
+        z += (theta - t * k.ln()).cos() / k.sqrt();
+    }
+    z *= 2.;
+
+    let denom = (2. * PI * p).cos();
+    let c0 = if denom.abs() < 1e-6 {
+        // removable singularity of the correction term at p = 1/4 and 3/4, where it tends to 1/2
+        0.5
+    } else {
+        (2. * PI * (p * p - p - 1. / 16.)).cos() / denom
+    };
+    let sign = if n % 2 == 1 { 1. } else { -1. };
+
+    z + sign * (t / (2. * PI)).powf(-0.25) * c0
+}
+
+fn zeta_table() -> &'static [f64] {
+    static TABLE: OnceLock<Vec<f64>> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        (0..=(ZETA_TABLE_END / ZETA_STEP) as usize)
+            .map(|i| riemann_siegel_z(i as f64 * ZETA_STEP))
+            .collect()
+    })
+}
+
+/// Z(t) interpolated from the table.
+fn zeta_z(t: f64) -> f64 {
+    if t >= ZETA_TABLE_END {
+        return ZETA_CACHE.with(|cache| {
+            let mut cache = cache.borrow_mut();
+            if let Some(&z) = cache.get(&t.to_bits()) {
+                return z;
+            }
+            if cache.len() >= ZETA_CACHE_MAX {
+                cache.clear();
+            }
+            let z = riemann_siegel_z(t);
+            cache.insert(t.to_bits(), z);
+            z
+        });
+    }
+    let table = zeta_table();
+    let pos = t / ZETA_STEP;
+    let i = pos.floor() as usize;
+    let frac = pos - i as f64;
+    table[i] * (1. - frac) + table[i + 1] * frac
+}
+
+struct RZvars {
+    c1: Variable<FirstFreeCost<ExponentialCost>, StepwiseValue>,
+    c2: Variable<ExponentialCost, ExponentialValue>,
+    b: Variable<ExponentialCost, LinearValue>,
+    w1: Variable<ExponentialCost, StepwiseValue>,
+}
+
+impl RZvars {
+    fn init() -> Self {
+        RZvars {
+            c1: Variable::new(
+                FirstFreeCost {
+                    model: ExponentialCost::new(225., 2f64.powf(0.699)),
+                },
+                StepwiseValue::new(2., 8),
+            ),
+            c2: Variable::new(
+                ExponentialCost::new(1500., 2f64.powf(0.699 * 4.)),
+                ExponentialValue::new(2.),
+            ),
+            b: Variable::new(ExponentialCost::new(1e21, 1e11), LinearValue::new(0.5, 0.)),
+            w1: Variable::new(
+                ExponentialCost::new(1e5, 10.),
+                StepwiseValue::new_offset(2., 8, 1.),
+            ),
+        }
+    }
+
+    fn getm(&mut self, id: usize) -> &mut dyn VariableTrait {
+        match id {
+            0 => &mut self.c1,
+            1 => &mut self.c2,
+            2 => &mut self.b,
+            _ => &mut self.w1,
+        }
+    }
+
+    fn get(&self, id: usize) -> &dyn VariableTrait {
+        match id {
+            0 => &self.c1,
+            1 => &self.c2,
+            2 => &self.b,
+            _ => &self.w1,
+        }
+    }
+
+    fn set(&mut self, lvls: [u32; 4]) {
+        for (i, level) in lvls.iter().enumerate() {
+            self.getm(i).set(*level);
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct RZdata {
+    caps: [u32; 4],
+    /// Milestone levels, in `MILESTONES` order.
+    pub milestones: [u32; 2],
+    /// Derive the milestones from rho and tau instead of keeping the given ones.
+    pub derive_milestones: bool,
+    pub do_coasting: bool,
+    /// With the black hole milestone, t stops at the first zero of zeta reached after this.
+    pub black_hole_t: f64,
+}
+
+pub struct RZstate {
+    pub levels: [u32; 4],
+    pub tvar: f64,
+}
+
+pub struct RZ {
+    data: TheoryData,
+    pub rzdata: RZdata,
+    pub goal: f64,
+    rho: f64,
+    maxrho: f64,
+    multiplier: f64,
+    tvar: f64,
+    zeta: f64,
+    in_black_hole: bool,
+    vars: RZvars,
+    varbuys: Vec<VarBuy>,
+
+    t: f64,
+    dt: f64,
+    ddt: f64,
+    depth: u32,
+
+    best_res: SimRes,
+}
+
+impl RZ {
+    pub fn new(data: TheoryData, goal: f64, state: Option<RZstate>) -> Self {
+        let mut rz: RZ = RZ {
+            data,
+            rzdata: RZdata {
+                caps: [u32::MAX; 4],
+                milestones: MILESTONES.max_levels,
+                derive_milestones: true,
+                do_coasting: true,
+                black_hole_t: 0.,
+            },
+            goal,
+            rho: 0.,
+            maxrho: 0.,
+            multiplier: 0.,
+            tvar: 0.,
+            zeta: 0.,
+            in_black_hole: false,
+            vars: RZvars::init(),
+            varbuys: Vec::new(),
+
+            t: 0.,
+            dt: 1.5,
+            ddt: 1.0001,
+            depth: 0,
+
+            best_res: SimRes::default(),
+        };
+
+        if let Some(state) = state {
+            rz.vars.set(state.levels);
+            rz.tvar = state.tvar;
+        }
+
+        rz.rho = rz.data.rho;
+        rz.multiplier = rz.get_multiplier(rz.data.tau);
+        rz.zeta = zeta_z(rz.tvar);
+
+        rz
+    }
+
+    pub fn fork(&self) -> Self {
+        let mut new: RZ = RZ {
+            data: self.data,
+            rzdata: self.rzdata,
+            goal: self.goal,
+            rho: self.rho,
+            maxrho: self.maxrho,
+            multiplier: self.multiplier,
+            tvar: self.tvar,
+            zeta: self.zeta,
+            in_black_hole: self.in_black_hole,
+            vars: RZvars::init(),
+            varbuys: self.varbuys.clone(),
+            t: self.t,
+            dt: self.dt,
+            ddt: self.ddt,
+            depth: self.depth + 1,
+
+            best_res: SimRes::default(),
+        };
+
+        for i in 0..4 {
+            new.vars.getm(i).set(self.vars.get(i).get_level())
+        }
+
+        new
+    }
+
+    /// Milestones reached with the max of rho and the last pub's rho.
+    fn update_milestones(&mut self) {
+        if self.rzdata.derive_milestones {
+            let rho = self.maxrho.max(self.data.tau * 2.5);
+            self.rzdata.milestones = MILESTONES.at(rho);
+        }
+    }
+
+    fn get_multiplier(&self, tau: f64) -> f64 {
+        tau * 1.01 - 7.
+    }
+
+    fn eval_coast_one(&self, dist: f64, lbound: f64, ubound: f64) -> BuyEval {
+        if dist > ubound {
+            BuyEval::BUY
+        } else if dist > lbound {
+            BuyEval::FORK
+        } else {
+            BuyEval::SKIP
+        }
+    }
+
+    fn eval_coast(&self, id: usize, cost: f64) -> BuyEval {
+        let dist: f64 = self.goal - cost;
+        if dist > 3. || !self.rzdata.do_coasting {
+            return BuyEval::BUY;
+        }
+        match id {
+            0 => self.eval_coast_one(dist, 0.4, 1.2),
+            1 => self.eval_coast_one(dist, 0.15, 0.6),
+            2 => self.eval_coast_one(dist, 0.3, 1.),
+            3 => self.eval_coast_one(dist, 0.4, 1.2),
+            _ => BuyEval::SKIP,
+        }
+    }
+
+    fn eval_ratio(&self, id: usize) -> BuyEval {
+        if match id {
+            0 => {
+                self.vars.c1.cost + (6. + (self.vars.c1.level % 8) as f64).log10()
+                    < self.vars.c2.cost
+            }
+            1 => true,
+            // b stops paying off once the 0.01 floor dominates the denominator
+            2 => self.in_black_hole || self.vars.b.value * 2f64.log10() < 4.,
+            3 => {
+                self.vars.w1.cost + (4. + (self.vars.w1.level % 8) as f64).log10()
+                    < self.vars.c2.cost
+            }
+            _ => false,
+        } {
+            BuyEval::BUY
+        } else {
+            BuyEval::SKIP
+        }
+    }
+
+    fn advance_t(&mut self) {
+        if self.in_black_hole {
+            return;
+        }
+        let prev_t = self.tvar;
+        let prev_zeta = self.zeta;
+        self.tvar += self.dt / 1.5;
+        self.zeta = zeta_z(self.tvar);
+
+        if self.rzdata.milestones[1] >= 1
+            && self.tvar >= self.rzdata.black_hole_t
+            && prev_zeta * self.zeta <= 0.
+        {
+            self.tvar = prev_t + (self.tvar - prev_t) * prev_zeta / (prev_zeta - self.zeta);
+            self.zeta = 0.;
+            self.in_black_hole = true;
+        }
+    }
+
+    fn tick(&mut self) {
+        self.advance_t();
+
+        let denom = log10add(
+            self.zeta.abs().log10() - self.vars.b.value * 2f64.log10(),
+            -2.,
+        );
+        let rhodot = self.tvar.log10()
+            + self.vars.c1.value * (1. + 0.05 * self.rzdata.milestones[0] as f64)
+            + self.vars.c2.value
+            + self.vars.w1.value
+            - denom;
+
+        self.rho = log10add(self.rho, rhodot + self.multiplier + self.dt.log10());
+        self.maxrho = self.maxrho.max(self.rho);
+
+        self.t += self.dt / 1.5;
+        self.dt *= self.ddt;
+    }
+
+    pub fn simulate(&mut self) -> SimRes {
+        while self.maxrho < self.goal {
+            self.update_milestones();
+            self.tick();
+            self.buy();
+        }
+
+        if self.t < self.best_res.t {
+            SimRes {
+                t: self.t,
+                var_buys: Some(self.varbuys.clone()),
+            }
+        } else {
+            SimRes {
+                t: self.best_res.t,
+                var_buys: self.best_res.var_buys.clone(),
+            }
+        }
+    }
+
+    fn buy(&mut self) {
+        let mut cost: f64;
+        let mut coast_eval: BuyEval;
+        let mut ratio_eval: BuyEval;
+        let variables: [(usize, &str); 4] = [(3, "w1"), (2, "b"), (1, "c2"), (0, "c1")];
+
+        for (id, name) in variables {
+            if self.vars.get(id).get_level() >= self.rzdata.caps[id] {
+                continue;
+            }
+
+            cost = self.vars.get(id).get_cost();
+
+            while self.rho > cost {
+                coast_eval = self.eval_coast(id, cost);
+                ratio_eval = self.eval_ratio(id);
+
+                if coast_eval != BuyEval::SKIP {
+                    if ratio_eval == BuyEval::SKIP {
+                        break;
+                    }
+                    if coast_eval == BuyEval::FORK {
+                        let mut fork: RZ = self.fork();
+                        let lvl: u32 = self.vars.get(id).get_level();
+                        fork.rzdata.caps[id] = lvl;
+                        let res: SimRes = fork.simulate();
+                        if res.t < self.best_res.t {
+                            self.best_res.t = res.t;
+                            self.best_res.var_buys = res.var_buys;
+                        }
+                    }
+
+                    self.rho = log10sub(self.rho, cost);
+                    self.vars.getm(id).buy();
+                    cost = self.vars.get(id).get_cost();
+
+                    if self.maxrho > self.data.tau * 2.5 - 5. {
+                        self.varbuys.push(VarBuy {
+                            symb: s!(name),
+                            lvl: self.vars.get(id).get_level(),
+                            t: self.t,
+                        })
+                    }
+                } else {
+                    self.rzdata.caps[id] = self.vars.get(id).get_level();
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Zero of Z between a and b, by bisection.
+    fn find_zero(mut a: f64, mut b: f64) -> f64 {
+        for _ in 0..60 {
+            let mid = (a + b) / 2.;
+            if riemann_siegel_z(a) * riemann_siegel_z(mid) <= 0. {
+                b = mid;
+            } else {
+                a = mid;
+            }
+        }
+        a
+    }
+
+    #[test]
+    fn riemann_siegel_z_first_zeros() {
+        for (a, b, zero) in [
+            (13.5, 15., 14.134725),
+            (20.5, 21.5, 21.022040),
+            (24.5, 25.5, 25.010858),
+        ] {
+            let found = find_zero(a, b);
+            assert!(
+                (found - zero).abs() < 0.01,
+                "zero at {found}, expected {zero}"
+            );
+        }
+    }
+
+    #[test]
+    fn riemann_siegel_z_continuous_at_removable_singularity() {
+        // a = sqrt(t / 2pi) = 1.25 puts p at 1/4
+        let t = 2. * PI * 1.25f64.powi(2);
+        let z = riemann_siegel_z(t);
+        assert!((riemann_siegel_z(t - 1e-4) - z).abs() < 1e-3);
+        assert!((riemann_siegel_z(t + 1e-4) - z).abs() < 1e-3);
+    }
+
+    #[test]
+    fn zeta_z_matches_direct_evaluation() {
+        for t in [14.134725, 1e3 + 0.025, 4.99e4] {
+            assert!((zeta_z(t) - riemann_siegel_z(t)).abs() < 0.02, "t = {t}");
+        }
+        // past the table, cached values are exact and stay so on the second lookup
+        for t in [5e4, 1.2345e5] {
+            assert_eq!(zeta_z(t), riemann_siegel_z(t));
+            assert_eq!(zeta_z(t), riemann_siegel_z(t));
+        }
+    }
+}
@@ -0,0 +1,394 @@
+use crate::s;
+use crate::utils::*;
+
+const EULER_GAMMA: f64 = 0.5772156649015329;
+
+/// Milestone tree: a1 exponent and b2 unlock.
+pub const MILESTONES: Milestones<2> = Milestones {
+    points: &[20., 40., 60., 100.],
+    max_levels: [3, 1],
+    order: [0, 1],
+};
+
+/// Harmonic number H_n, from log10(n). Exact for small n, asymptotic expansion otherwise.
+fn harmonic(logn: f64) -> f64 {
+    if logn < 2. {
+        let n = 10f64.powf(logn).round() as u32;
+        return (1..=n.max(1)).map(|k| 1. / k as f64).sum();
+    }
+    let n = 10f64.powf(logn);
+    logn * 10f64.ln() + EULER_GAMMA + 1. / (2. * n) - 1. / (12. * n * n)
+}
+
+struct SLvars {
+    a1: Variable<FirstFreeCost<ExponentialCost>, StepwiseValue>,
+    a2: Variable<ExponentialCost, ExponentialValue>,
+    b1: Variable<ExponentialCost, LinearValue>,
+    b2: Variable<ExponentialCost, ExponentialValue>,
+}
+
+impl SLvars {
+    fn init() -> Self {
+        SLvars {
+            a1: Variable::new(
+                FirstFreeCost {
+                    model: ExponentialCost::new(10., 1.6),
+                },
+                StepwiseValue::new(2., 10),
+            ),
+            a2: Variable::new(ExponentialCost::new(50., 12.), ExponentialValue::new(2.)),
+            b1: Variable::new(ExponentialCost::new(100., 2.), LinearValue::new(1., 1.)),
+            b2: Variable::new(ExponentialCost::new(1e4, 8.), ExponentialValue::new(2.)),
+        }
+    }
+
+    fn getm(&mut self, id: usize) -> &mut dyn VariableTrait {
+        match id {
+            0 => &mut self.a1,
+            1 => &mut self.a2,
+            2 => &mut self.b1,
+            _ => &mut self.b2,
+        }
+    }
+
+    fn get(&self, id: usize) -> &dyn VariableTrait {
+        match id {
+            0 => &self.a1,
+            1 => &self.a2,
+            2 => &self.b1,
+            _ => &self.b2,
+        }
+    }
+
+    fn set(&mut self, lvls: [u32; 4]) {
+        for (i, level) in lvls.iter().enumerate() {
+            self.getm(i).set(*level);
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct SLdata {
+    caps: [u32; 4],
+    /// Milestone levels, in `MILESTONES` order.
+    pub milestones: [u32; 2],
+    /// Derive the milestones from rho and tau instead of keeping the given ones.
+    pub derive_milestones: bool,
+    pub do_coasting: bool,
+}
+
+pub struct SLstate {
+    pub levels: [u32; 4],
+}
+
+pub struct SL {
+    data: TheoryData,
+    pub sldata: SLdata,
+    pub goal: f64,
+    rho: f64,
+    maxrho: f64,
+    multiplier: f64,
+    harmonic: f64,
+    vars: SLvars,
+    varbuys: Vec<VarBuy>,
+
+    t: f64,
+    dt: f64,
+    ddt: f64,
+    depth: u32,
+
+    best_res: SimRes,
+}
+
+impl SL {
+    pub fn new(data: TheoryData, goal: f64, state: Option<SLstate>) -> Self {
+        let mut sl: SL = SL {
+            data,
+            sldata: SLdata {
+                caps: [u32::MAX; 4],
+                milestones: MILESTONES.max_levels,
+                derive_milestones: true,
+                do_coasting: true,
+            },
+            goal,
+            rho: 0.,
+            maxrho: 0.,
+            multiplier: 0.,
+            harmonic: 1.,
+            vars: SLvars::init(),
+            varbuys: Vec::new(),
+
+            t: 0.,
+            dt: 1.5,
+            ddt: 1.0001,
+            depth: 0,
+
+            best_res: SimRes::default(),
+        };
+
+        if let Some(state) = state {
+            sl.vars.set(state.levels);
+        }
+
+        sl.rho = sl.data.rho;
+        sl.multiplier = sl.get_multiplier(sl.data.tau);
+        sl.harmonic = harmonic(sl.get_logn());
+
+        sl
+    }
+
+    pub fn fork(&self) -> Self {
+        let mut new: SL = SL {
+            data: self.data,
+            sldata: self.sldata,
+            goal: self.goal,
+            rho: self.rho,
+            maxrho: self.maxrho,
+            multiplier: self.multiplier,
+            harmonic: self.harmonic,
+            vars: SLvars::init(),
+            varbuys: self.varbuys.clone(),
+            t: self.t,
+            dt: self.dt,
+            ddt: self.ddt,
+            depth: self.depth + 1,
+
+            best_res: SimRes::default(),
+        };
+
+        for i in 0..4 {
+            new.vars.getm(i).set(self.vars.get(i).get_level())
+        }
+
+        new
+    }
+
+    /// Milestones reached with the max of rho and the last pub's rho.
+    /// The b2 unlock changes n, so the cached harmonic number is recomputed.
+    fn update_milestones(&mut self) {
+        if !self.sldata.derive_milestones {
+            return;
+        }
+        let milestones = MILESTONES.at(self.maxrho.max(self.data.tau * 2.));
+        if milestones != self.sldata.milestones {
+            self.sldata.milestones = milestones;
+            self.harmonic = harmonic(self.get_logn());
+        }
+    }
+
+    fn get_multiplier(&self, tau: f64) -> f64 {
+        tau * 1.1 - 3.
+    }
+
+    /// log10 of the harmonic index n = b1 * b2.
+    fn get_logn(&self) -> f64 {
+        self.vars.b1.value.log10()
+            + if self.sldata.milestones[1] >= 1 {
+                self.vars.b2.value
+            } else {
+                0.
+            }
+    }
+
+    /// Exponent applied to the a-term, H_n / (1 + H_n).
+    fn get_exponent(&self, harmonic: f64) -> f64 {
+        harmonic / (1. + harmonic)
+    }
+
+    fn get_aterm(&self) -> f64 {
+        self.vars.a1.value * (1. + 0.05 * self.sldata.milestones[0] as f64) + self.vars.a2.value
+    }
+
+    fn get_variable_conditions(&self, id: usize) -> bool {
+        match id {
+            0..=2 => true,
+            3 => self.sldata.milestones[1] >= 1,
+            _ => false,
+        }
+    }
+
+    fn eval_coast_one(&self, dist: f64, lbound: f64, ubound: f64) -> BuyEval {
+        if dist > ubound {
+            BuyEval::BUY
+        } else if dist > lbound {
+            BuyEval::FORK
+        } else {
+            BuyEval::SKIP
+        }
+    }
+
+    fn eval_coast(&self, id: usize, cost: f64) -> BuyEval {
+        let dist: f64 = self.goal - cost;
+        if dist > 3. || !self.sldata.do_coasting {
+            return BuyEval::BUY;
+        }
+        match id {
+            0 => self.eval_coast_one(dist, 0.4, 1.4),
+            1 => self.eval_coast_one(dist, 0.15, 0.6),
+            // b levels only pay off over a long horizon
+            _ => BuyEval::SKIP,
+        }
+    }
+
+    /// Gain in log10(rhodot) per unit of rho spent, in log10.
+    /// b levels only pay off through the exponent on the a-term, so they
+    /// are compared against a2 by efficiency instead of being autobought.
+    fn get_efficiency(&self, id: usize) -> f64 {
+        let exponent = self.get_exponent(self.harmonic);
+        let gain: f64 = match id {
+            1 => exponent * 2f64.log10(),
+            2 | 3 => {
+                let mut logn = self.get_logn();
+                if id == 2 {
+                    logn += ((self.vars.b1.value + 1.) / self.vars.b1.value).log10();
+                } else {
+                    logn += 2f64.log10();
+                }
+                (self.get_exponent(harmonic(logn)) - exponent) * self.get_aterm()
+            }
+            _ => 0.,
+        };
+        gain.log10() - self.vars.get(id).get_cost()
+    }
+
+    fn eval_ratio(&self, id: usize) -> BuyEval {
+        if match id {
+            0 => {
+                self.vars.a1.cost + (7. + (self.vars.a1.level % 10) as f64).log10()
+                    < self.vars.a2.cost
+            }
+            1 => true,
+            2 | 3 => self.get_efficiency(id) >= self.get_efficiency(1),
+            _ => false,
+        } {
+            BuyEval::BUY
+        } else {
+            BuyEval::SKIP
+        }
+    }
+
+    fn tick(&mut self) {
+        let rhodot = self.get_exponent(self.harmonic) * self.get_aterm();
+
+        self.rho = log10add(self.rho, rhodot + self.multiplier + self.dt.log10());
+        self.maxrho = self.maxrho.max(self.rho);
+
+        self.t += self.dt / 1.5;
+        self.dt *= self.ddt;
+    }
+
+    pub fn simulate(&mut self) -> SimRes {
+        while self.maxrho < self.goal {
+            self.update_milestones();
+            self.tick();
+            self.buy();
+        }
+
+        if self.t < self.best_res.t {
+            SimRes {
+                t: self.t,
+                var_buys: Some(self.varbuys.clone()),
+            }
+        } else {
+            SimRes {
+                t: self.best_res.t,
+                var_buys: self.best_res.var_buys.clone(),
+            }
+        }
+    }
+
+    fn buy(&mut self) {
+        let mut cost: f64;
+        let mut coast_eval: BuyEval;
+        let mut ratio_eval: BuyEval;
+        let variables: [(usize, &str); 4] = [(3, "b2"), (2, "b1"), (1, "a2"), (0, "a1")];
+
+        for (id, name) in variables {
+            if self.vars.get(id).get_level() >= self.sldata.caps[id]
+                || !self.get_variable_conditions(id)
+            {
+                continue;
+            }
+
+            cost = self.vars.get(id).get_cost();
+
+            while self.rho > cost {
+                coast_eval = self.eval_coast(id, cost);
+                ratio_eval = self.eval_ratio(id);
+
+                if coast_eval != BuyEval::SKIP {
+                    if ratio_eval == BuyEval::SKIP {
+                        break;
+                    }
+                    if coast_eval == BuyEval::FORK {
+                        let mut fork: SL = self.fork();
+                        let lvl: u32 = self.vars.get(id).get_level();
+                        fork.sldata.caps[id] = lvl;
+                        let res: SimRes = fork.simulate();
+                        if res.t < self.best_res.t {
+                            self.best_res.t = res.t;
+                            self.best_res.var_buys = res.var_buys;
+                        }
+                    }
+
+                    self.rho = log10sub(self.rho, cost);
+                    self.vars.getm(id).buy();
+                    cost = self.vars.get(id).get_cost();
+                    if id >= 2 {
+                        self.harmonic = harmonic(self.get_logn());
+                    }
+
+                    if self.maxrho > self.data.tau * 2. - 5. {
+                        self.varbuys.push(VarBuy {
+                            symb: s!(name),
+                            lvl: self.vars.get(id).get_level(),
+                            t: self.t,
+                        })
+                    }
+                } else {
+                    self.sldata.caps[id] = self.vars.get(id).get_level();
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn harmonic_exact_meets_asymptotic() {
+        // H_100 = 5.18737751763962
+        let exact: f64 = (1..=100).map(|k| 1. / k as f64).sum();
+        assert!((harmonic(2.) - exact).abs() < 1e-9);
+        assert!((harmonic(99f64.log10()) - (exact - 0.01)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn harmonic_recomputed_on_b2_unlock() {
+        let mut sl = SL::new(
+            TheoryData {
+                tau: 10.,
+                students: 0,
+                rho: 0.,
+            },
+            200.,
+            Some(SLstate {
+                levels: [0, 0, 10, 5],
+            }),
+        );
+        sl.update_milestones();
+        assert_eq!(sl.sldata.milestones, [1, 0]);
+        assert!((sl.harmonic - harmonic(11f64.log10())).abs() < 1e-12);
+        let before = sl.harmonic;
+
+        sl.maxrho = 100.;
+        sl.update_milestones();
+        assert_eq!(sl.sldata.milestones, [3, 1]);
+        // n goes from b1 = 11 to b1 * b2 = 352
+        assert!((sl.harmonic - harmonic(352f64.log10())).abs() < 1e-12);
+        assert!(sl.harmonic > before);
+    }
+}
@@ -1,11 +1,60 @@
+use std::str::FromStr;
+
 use crate::s;
 use crate::utils::*;
 
+/// Milestone tree: log term, c3 exponent and c4 exponent.
+pub const MILESTONES: Milestones<3> = Milestones {
+    points: &[25., 50., 75., 100., 125., 150., 175., 200.],
+    max_levels: [1, 4, 3],
+    order: [0, 1, 2],
+};
+
+/// Purchase strategies for T1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum T1strat {
+    /// Buys everything, coasting forks on q1, q2 and c3 near the goal.
+    C34,
+    /// Buys the variable with the lowest weighted cost, see `eval_buy`.
+    Ratio,
+    /// Late game, c3 is no longer bought.
+    C4,
+    /// Pure autobuy, no coasting.
+    Idle,
+}
+
+impl T1strat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            T1strat::C34 => "c34",
+            T1strat::Ratio => "ratio",
+            T1strat::C4 => "c4",
+            T1strat::Idle => "idle",
+        }
+    }
+}
+
+impl FromStr for T1strat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &s.to_lowercase()[..] {
+            "c34" => Ok(T1strat::C34),
+            "ratio" => Ok(T1strat::Ratio),
+            "c4" => Ok(T1strat::C4),
+            "idle" => Ok(T1strat::Idle),
+            _ => Err(format!("Unknown T1 strategy: {s}")),
+        }
+    }
+}
+
 struct T1vars {
     q1: Variable<FirstFreeCost<ExponentialCost>, StepwiseValue>,
     q2: Variable<ExponentialCost, ExponentialValue>,
     c3: Variable<ExponentialCost, ExponentialValue>,
     c4: Variable<ExponentialCost, ExponentialValue>,
+    c1: Variable<ExponentialCost, ExponentialValue>,
+    c2: Variable<ExponentialCost, ExponentialValue>,
 }
 
 impl T1vars {
@@ -26,6 +75,14 @@ impl T1vars {
                 ExponentialCost::new(1e10, 10f64.powi(8)),
                 ExponentialValue::new(10.),
             ),
+            c1: Variable::new(
+                ExponentialCost::new(1e3, 10f64.powf(1.5)),
+                ExponentialValue::new(2.),
+            ),
+            c2: Variable::new(
+                ExponentialCost::new(1e4, 10f64.powf(2.5)),
+                ExponentialValue::new(2.),
+            ),
         }
     }
 
@@ -34,7 +91,9 @@ impl T1vars {
             0 => &mut self.q1,
             1 => &mut self.q2,
             2 => &mut self.c3,
-            _ => &mut self.c4,
+            3 => &mut self.c4,
+            4 => &mut self.c1,
+            _ => &mut self.c2,
         }
     }
 
@@ -43,11 +102,13 @@ impl T1vars {
             0 => &self.q1,
             1 => &self.q2,
             2 => &self.c3,
-            _ => &self.c4,
+            3 => &self.c4,
+            4 => &self.c1,
+            _ => &self.c2,
         }
     }
 
-    fn set(&mut self, lvls: [u32; 4]) {
+    fn set(&mut self, lvls: [u32; 6]) {
         for (i, level) in lvls.iter().enumerate() {
             self.getm(i).set(*level);
         }
@@ -56,12 +117,17 @@ impl T1vars {
 
 #[derive(Clone, Copy)]
 pub struct T1data {
-    pub caps: [u32; 4],
+    pub caps: [u32; 6],
+    /// Milestone levels, in `MILESTONES` order.
+    pub milestones: [u32; 3],
+    /// Derive the milestones from rho and tau instead of keeping the given ones.
+    pub derive_milestones: bool,
     pub do_coasting: bool,
+    pub strat: T1strat,
 }
 
 pub struct T1state {
-    pub levels: [u32; 4],
+    pub levels: [u32; 6],
 }
 
 pub struct T1 {
@@ -87,8 +153,11 @@ impl T1 {
         let mut t1: T1 = T1 {
             data: data,
             t1data: T1data {
-                caps: [u32::MAX; 4],
-                do_coasting: true 
+                caps: [u32::MAX; 6],
+                milestones: MILESTONES.max_levels,
+                derive_milestones: true,
+                do_coasting: true,
+                strat: T1strat::C34,
             },
             goal: goal,
             rho: 0.,
@@ -136,39 +205,74 @@ impl T1 {
             best_res: SimRes::default(),
         };
 
-        for i in 0..4 {
+        for i in 0..6 {
             new.vars.getm(i).set(self.vars.get(i).get_level())
         }
 
         new
     }
 
+    /// Milestones reached with the max of rho and the last pub's rho.
+    fn update_milestones(&mut self) {
+        if self.t1data.derive_milestones {
+            let rho = self.maxrho.max(self.data.tau);
+            self.t1data.milestones = MILESTONES.at(rho);
+        }
+    }
+
     fn get_multiplier(&self, tau: f64, sigma: u32) -> f64 {
         tau * 0.164 - 3f64.log10() + 3. * (sigma as f64 / 20.).log10()
     }
 
+    fn get_c3_exponent(&self) -> f64 {
+        0.05 * self.t1data.milestones[1] as f64
+    }
+
+    fn get_c4_exponent(&self) -> f64 {
+        0.1 * self.t1data.milestones[2] as f64
+    }
+
+    /// log10 of 1 + ln(rho) / 100 on the c1 c2 term, once the log term milestone is owned.
+    fn get_log_term(&self) -> f64 {
+        if self.t1data.milestones[0] == 0 || self.rho <= 0. {
+            0.
+        } else {
+            (1. + self.rho * 10f64.ln() / 100.).log10()
+        }
+    }
+
     fn eval_buy(&self, id: usize) -> BuyEval {
-        //return BuyEval::BUY;
-        let c3term = self.vars.c3.value + 0.2 * self.rho;
-        let c4term = self.vars.c4.value + 0.3 * self.rho;
-        let term_sum = log10add(c3term, c4term);
+        let c12term = self.vars.c1.value + self.vars.c2.value + self.get_log_term();
+        let c3term = self.vars.c3.value + self.get_c3_exponent() * self.rho;
+        let c4term = self.vars.c4.value + self.get_c4_exponent() * self.rho;
+        let term_sum = log10add(log10add(c3term, c4term), c12term);
+        let c12_ratio = 10f64.powf(c12term - term_sum);
         let c3_ratio = 10f64.powf(c3term - term_sum);
         let c4_ratio = 10f64.powf(c4term - term_sum);
+        // growth is dominated by the largest rho exponent
+        let p: f64 = self.get_c3_exponent().max(self.get_c4_exponent()).max(0.05);
 
-        let multipliers: [f64; 4] = [
+        let multipliers: [f64; 6] = [
             (11. + (self.vars.q1.level % 10) as f64) / (10. + (self.vars.q1.level % 10) as f64),
             2.,
-            10. * c3_ratio + c4_ratio,
-            c3_ratio + 10. * c4_ratio,
+            1. + 9. * c3_ratio,
+            1. + 9. * c4_ratio,
+            1. + c12_ratio,
+            1. + c12_ratio,
         ];
 
-        let mut weighted_costs: [f64; 4] = [0.; 4];
+        let mut weighted_costs: [f64; 6] = [0.; 6];
 
-        for i in 0..4 {
+        for i in 0..6 {
+            if multipliers[i] <= 1. {
+                weighted_costs[i] = f64::MAX;
+                continue;
+            }
             weighted_costs[i] = self.vars.get(i).get_cost()
-                + (1. / 0.7)
-                    * ((-1. / (multipliers[i] * (multipliers[i].powf(1. / 0.3) - 1.).powf(0.7))
-                        + 1. / (1. - 1. / multipliers[i].powf(1. / 0.3)).powf(0.7))
+                + (1. / (1. - p))
+                    * ((-1.
+                        / (multipliers[i] * (multipliers[i].powf(1. / p) - 1.).powf(1. - p))
+                        + 1. / (1. - 1. / multipliers[i].powf(1. / p)).powf(1. - p))
                         / (1. - 1. / multipliers[i]))
                         .log10()
         }
@@ -177,15 +281,13 @@ impl T1 {
             return BuyEval::SKIP;
         };
 
-        let min_wcost = weighted_costs[0]
-            .min(weighted_costs[1])
-            .min(weighted_costs[2])
-            .min(weighted_costs[3]);
-        let mult2 = multipliers[id].powf(10. / 3.);
+        let min_wcost = weighted_costs.iter().fold(f64::MAX, |a, b| a.min(*b));
+        let mult2 = multipliers[id].powf(1. / p);
 
         //println!("{} {} {}", id, weighted_costs[id], min_wcost);
 
-        if weighted_costs[id] < min_wcost + 0.0001
+        // <= so that the free first q1 level (cost -inf) is bought
+        if weighted_costs[id] <= min_wcost + 0.0001
             && self.rho > self.vars.get(id).get_cost() + (1. / (1. - 1. / mult2)).log10()
         {
             BuyEval::BUY
@@ -196,7 +298,7 @@ impl T1 {
 
     fn eval_coast(&self, id: usize, cost: f64) -> BuyEval {
         let dist: f64 = self.goal - cost;
-        if dist > 6. || !self.t1data.do_coasting {
+        if dist > 6. || !self.t1data.do_coasting || self.t1data.strat == T1strat::Idle {
             return BuyEval::BUY;
         }
         match id {
@@ -228,12 +330,17 @@ impl T1 {
     }
 
     fn tick(&mut self) {
+        let terms: f64 = log10add(
+            self.vars.c1.value + self.vars.c2.value + self.get_log_term(),
+            log10add(
+                self.vars.c3.value + self.rho * self.get_c3_exponent(),
+                self.vars.c4.value + self.rho * self.get_c4_exponent(),
+            ),
+        );
         self.rho = log10add(
             self.rho,
-            log10add(
-                self.vars.c3.value + self.rho * 0.2,
-                self.vars.c4.value + self.rho * 0.3,
-            ) + self.vars.q1.value
+            terms
+                + self.vars.q1.value
                 + self.vars.q2.value
                 + self.multiplier
                 + self.dt.log10(),
@@ -246,6 +353,7 @@ impl T1 {
 
     pub fn simulate(&mut self) -> SimRes {
         while self.maxrho < self.goal {
+            self.update_milestones();
             self.tick();
             self.buy();
         }
@@ -267,10 +375,19 @@ impl T1 {
         let mut cost: f64;
         let mut coast_eval: BuyEval;
         let mut ratio_eval: BuyEval;
-        let variables: [(usize, &str); 4] = [(3, "c4"), (2, "c3"), (1, "q2"), (0, "q1")];
+        let variables: [(usize, &str); 6] = [
+            (5, "c2"),
+            (4, "c1"),
+            (3, "c4"),
+            (2, "c3"),
+            (1, "q2"),
+            (0, "q1"),
+        ];
 
         for (id, name) in variables {
-            if self.vars.get(id).get_level() >= self.t1data.caps[id] {
+            if self.vars.get(id).get_level() >= self.t1data.caps[id]
+                || (id == 2 && self.t1data.strat == T1strat::C4)
+            {
                 continue;
             }
 
@@ -278,7 +395,10 @@ impl T1 {
 
             while self.rho > cost {
                 coast_eval = self.eval_coast(id, cost);
-                ratio_eval = BuyEval::BUY;
+                ratio_eval = match self.t1data.strat {
+                    T1strat::Ratio => self.eval_buy(id),
+                    _ => BuyEval::BUY,
+                };
 
                 if coast_eval != BuyEval::SKIP {
                     if ratio_eval == BuyEval::SKIP {
@@ -1,6 +1,52 @@
+use std::str::FromStr;
+
 use crate::s;
 use crate::utils::*;
 
+/// Milestone tree: q layers, r layers, q1 exponent and r1 exponent.
+pub const MILESTONES: Milestones<4> = Milestones {
+    points: &[25., 50., 75., 100., 125., 150., 175., 200., 225., 250.],
+    max_levels: [2, 2, 3, 3],
+    order: [0, 1, 2, 3],
+};
+
+/// Increase of rho (log10) between two swap checks.
+const SWAP_STEP: f64 = 0.5;
+/// How far (log10 of rho) the forks of a swap check are run. A swap pays off only once
+/// the layers of the newly focused chain have grown, well after the next check.
+const SWAP_HORIZON: f64 = 2.;
+
+/// Purchase strategies for T2.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum T2strat {
+    /// Buys every layer as soon as it is affordable.
+    BuyAll,
+    /// Buys the layer with the lowest weighted cost, see `eval_ratio`. Slower than `BuyAll`
+    /// on fresh 10 rho pubs from tau 250 to 450 (500 students), by 139% down to 7%.
+    Ratio,
+}
+
+impl T2strat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            T2strat::BuyAll => "buyall",
+            T2strat::Ratio => "ratio",
+        }
+    }
+}
+
+impl FromStr for T2strat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &s.to_lowercase()[..] {
+            "buyall" => Ok(T2strat::BuyAll),
+            "ratio" => Ok(T2strat::Ratio),
+            _ => Err(format!("Unknown T2 strategy: {s}")),
+        }
+    }
+}
+
 struct T2vars {
     dq1: Variable<FirstFreeCost<ExponentialCost>, StepwiseValue>,
     dq2: Variable<ExponentialCost, StepwiseValue>,
@@ -65,8 +111,21 @@ impl T2vars {
 }
 
 #[derive(Clone)]
-struct T2data {
+pub struct T2data {
     caps: [u32; 8],
+    pub strat: T2strat,
+    /// Weight of the level mod 10 in the ratio strategy, for layers 1 to 4 of each chain.
+    pub ratio_coefs: [f64; 4],
+    /// Milestone points. The allocation swaps between the q and r chains when not every
+    /// milestone can be owned, see `check_swap`.
+    pub points: u32,
+    /// Derive the points from rho and tau instead of keeping the given ones.
+    pub derive_milestones: bool,
+    milestones: [u32; 4],
+    focus_r: bool,
+    next_swap_check: f64,
+    /// Runs only to the horizon of a swap check, without coasting forks.
+    lookahead: bool,
 }
 
 impl Copy for T2data {}
@@ -78,7 +137,7 @@ pub struct T2state {
 
 pub struct T2 {
     data: TheoryData,
-    t2data: T2data,
+    pub t2data: T2data,
     goal: f64,
     rho: f64,
     maxrho: f64,
@@ -101,6 +160,14 @@ impl T2 {
             data: data,
             t2data: T2data {
                 caps: [u32::MAX; 8],
+                strat: T2strat::BuyAll,
+                ratio_coefs: [0.24, 0.18, 0.12, 0.05],
+                points: 0,
+                derive_milestones: true,
+                milestones: MILESTONES.max_levels,
+                focus_r: false,
+                next_swap_check: 0.,
+                lookahead: false,
             },
             goal: goal,
             rho: 0.,
@@ -128,6 +195,7 @@ impl T2 {
 
         t2.rho = t2.data.rho;
         t2.multiplier = t2.get_multiplier(t2.data.tau, t2.data.students);
+        t2.t2data.points = MILESTONES.points_at(t2.data.tau);
 
         t2
     }
@@ -162,6 +230,84 @@ impl T2 {
         3. * (students as f64 / 20.).log10() + 0.198 * tau - 2.
     }
 
+    /// Points reached with the max of rho and the last pub's rho.
+    fn update_points(&mut self) {
+        if !self.t2data.derive_milestones {
+            return;
+        }
+        let points = MILESTONES.points_at(self.maxrho.max(self.data.tau));
+        if points != self.t2data.points {
+            self.t2data.points = points;
+            self.update_milestones();
+        }
+    }
+
+    /// Sets the allocation for the current focus. Layers of the focused chain
+    /// come first, then its exponent, then the other chain.
+    fn update_milestones(&mut self) {
+        let order: [usize; 4] = if self.t2data.focus_r {
+            [1, 3, 0, 2]
+        } else {
+            [0, 2, 1, 3]
+        };
+        self.t2data.milestones = MILESTONES.allocate(self.t2data.points, &order);
+    }
+
+    fn swap(&mut self) {
+        self.t2data.focus_r = !self.t2data.focus_r;
+        self.update_milestones();
+
+        if self.maxrho > self.goal - 9. {
+            self.varbuys.push(VarBuy {
+                symb: s!(if self.t2data.focus_r { "ms_r" } else { "ms_q" }),
+                lvl: self.t2data.milestones[if self.t2data.focus_r { 1 } else { 0 }],
+                t: self.t,
+            })
+        }
+    }
+
+    /// The focused chain is worth more as its layers catch up, so the best swap times
+    /// depend on the whole state. At each check, a fork that swaps and one that does not
+    /// are run `SWAP_HORIZON` ahead, and the focus of the faster one is kept.
+    fn check_swap(&mut self) {
+        if self.t2data.points >= MILESTONES.total()
+            || self.t2data.lookahead
+            || self.maxrho < self.t2data.next_swap_check
+        {
+            return;
+        }
+        self.t2data.next_swap_check = self.maxrho + SWAP_STEP;
+        let horizon: f64 = (self.maxrho + SWAP_HORIZON).min(self.goal);
+
+        let mut stay: T2 = self.fork();
+        stay.t2data.lookahead = true;
+        let mut swap: T2 = self.fork();
+        swap.t2data.lookahead = true;
+        swap.swap();
+        stay.run_to(horizon);
+        swap.run_to(horizon);
+
+        if swap.t < stay.t {
+            self.swap();
+        }
+    }
+
+    fn run_to(&mut self, rho: f64) {
+        while self.maxrho < rho {
+            self.update_points();
+            self.tick();
+            self.buy();
+        }
+    }
+
+    /// Layers 3 and 4 of each chain are unlocked by its layer milestone.
+    fn is_layer_active(&self, id: usize) -> bool {
+        match id % 4 {
+            0 | 1 => true,
+            k => self.t2data.milestones[id / 4] as usize >= k - 1,
+        }
+    }
+
     fn eval_coast_one(&self, dist: f64, lbound: f64, ubound: f64) -> BuyEval {
         if dist > ubound {
             BuyEval::BUY
@@ -187,23 +333,20 @@ impl T2 {
     }
 
     fn eval_ratio(&self, id: usize) -> BuyEval {
-        return BuyEval::BUY;
-
-        if self.goal - self.maxrho < 8. {
+        if self.t2data.strat == T2strat::BuyAll || self.goal - self.maxrho < 8. {
             return BuyEval::BUY;
         }
 
-        let mut costs: [f64; 8] = [0.; 8];
+        // locked layers keep their first cost and are never bought
+        let mut costs: [f64; 8] = [f64::MAX; 8];
         let mut levels: [u32; 8] = [1; 8];
         for i in 0..8 {
+            if !self.is_layer_active(i) {
+                continue;
+            }
             costs[i] = self.vars.get(i).get_cost();
             levels[i] = self.vars.get(i).get_level();
-            match i {
-                0 | 4 => costs[i] += (1f64 + 0.24 * ((levels[i] % 10) as f64)).log10(),
-                1 | 5 => costs[i] += (1f64 + 0.18 * ((levels[i] % 10) as f64)).log10(),
-                2 | 6 => costs[i] += (1f64 + 0.12 * ((levels[i] % 10) as f64)).log10(),
-                _ => costs[i] += (1f64 + 0.05 * ((levels[i] % 10) as f64)).log10(),
-            }
+            costs[i] += (1f64 + self.t2data.ratio_coefs[i % 4] * ((levels[i] % 10) as f64)).log10();
         }
 
         let mut bestind = 0;
@@ -223,18 +366,24 @@ impl T2 {
     fn tick(&mut self) {
         let logdt: f64 = self.dt.log10();
 
-        self.layers[0] = log10add(self.layers[0], self.vars.dq1.value + self.layers[1] + logdt);
-        self.layers[1] = log10add(self.layers[1], self.vars.dq2.value + self.layers[2] + logdt);
-        self.layers[2] = log10add(self.layers[2], self.vars.dq3.value + self.layers[3] + logdt);
-        self.layers[3] = log10add(self.layers[3], self.vars.dq4.value + logdt);
-        self.layers[4] = log10add(self.layers[4], self.vars.dr1.value + self.layers[5] + logdt);
-        self.layers[5] = log10add(self.layers[5], self.vars.dr2.value + self.layers[6] + logdt);
-        self.layers[6] = log10add(self.layers[6], self.vars.dr3.value + self.layers[7] + logdt);
-        self.layers[7] = log10add(self.layers[7], self.vars.dr4.value + logdt);
+        for i in 0..8 {
+            if !self.is_layer_active(i) {
+                continue;
+            }
+            let next: f64 = if i % 4 < 3 && self.is_layer_active(i + 1) {
+                self.layers[i + 1]
+            } else {
+                0.
+            };
+            self.layers[i] = log10add(self.layers[i], self.vars.get(i).get_value() + next + logdt);
+        }
 
         self.rho = log10add(
             self.rho,
-            self.layers[0] * 1.15 + self.layers[4] * 1.15 + self.multiplier + logdt,
+            self.layers[0] * (1. + 0.05 * self.t2data.milestones[2] as f64)
+                + self.layers[4] * (1. + 0.05 * self.t2data.milestones[3] as f64)
+                + self.multiplier
+                + logdt,
         );
         self.maxrho = self.maxrho.max(self.rho);
 
@@ -243,9 +392,13 @@ impl T2 {
     }
 
     pub fn simulate(&mut self) -> SimRes {
+        self.update_milestones();
+
         while self.maxrho < self.goal {
+            self.update_points();
             self.tick();
             self.buy();
+            self.check_swap();
         }
 
         if self.t < self.best_res.t {
@@ -270,7 +423,9 @@ impl T2 {
 
         for i in 0..8 {
             //if self.t2data.skip[ids[i]] { continue; }
-            if self.vars.get(ids[i]).get_level() >= self.t2data.caps[ids[i]] {
+            if self.vars.get(ids[i]).get_level() >= self.t2data.caps[ids[i]]
+                || !self.is_layer_active(ids[i])
+            {
                 continue;
             }
             cost = self.vars.get(ids[i]).get_cost();
@@ -281,7 +436,7 @@ impl T2 {
                     if ratio_eval == BuyEval::SKIP {
                         break;
                     }
-                    if coast_eval == BuyEval::FORK {
+                    if coast_eval == BuyEval::FORK && !self.t2data.lookahead {
                         let mut fork: T2 = self.fork();
                         let lvl: u32 = self.vars.get(ids[i]).get_level();
                         fork.t2data.caps[ids[i]] = lvl;
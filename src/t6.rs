@@ -1,5 +1,52 @@
+use std::str::FromStr;
+
 use crate::utils::*;
 
+/// Milestone tree: c1 exponent. The other milestones are taken as owned.
+pub const MILESTONES: Milestones<1> = Milestones {
+    points: &[25., 50., 75.],
+    max_levels: [3],
+    order: [0],
+};
+
+/// Purchase strategies for T6.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum T6strat {
+    /// Coasting on q, r and c5, c1 and c2 are never bought.
+    C5,
+    /// Coasting on q, r and c5, with c1 and c2.
+    C125,
+    /// Pure autobuy of q, r and c5.
+    NoC1234,
+    /// Coasting with ratio forks on q, r and c5, see `eval_ratio`.
+    Ratio,
+}
+
+impl T6strat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            T6strat::C5 => "c5",
+            T6strat::C125 => "c125",
+            T6strat::NoC1234 => "noc1234",
+            T6strat::Ratio => "ratio",
+        }
+    }
+}
+
+impl FromStr for T6strat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &s.to_lowercase()[..] {
+            "c5" => Ok(T6strat::C5),
+            "c125" => Ok(T6strat::C125),
+            "noc1234" => Ok(T6strat::NoC1234),
+            "ratio" => Ok(T6strat::Ratio),
+            _ => Err(format!("Unknown T6 strategy: {s}")),
+        }
+    }
+}
+
 struct T6vars {
     q1: Variable<FirstFreeCost<ExponentialCost>, StepwiseValue>,
     q2: Variable<ExponentialCost, ExponentialValue>,
@@ -38,7 +85,7 @@ impl T6vars {
         ]
     }
 
-    fn _iter_noc12_r(&mut self) -> Vec<&mut dyn VariableTrait> {
+    fn iter_noc12_r(&mut self) -> Vec<&mut dyn VariableTrait> {
         vec![
             &mut self.c5,
             &mut self.r2,
@@ -80,12 +127,17 @@ impl T6vars {
 }
 
 #[derive(Clone)]
-struct T6data {
+pub struct T6data {
     caps: [u32; 7],
     skip: [bool; 7],
     scale_start: f64,
     scale_end: f64,
     tol: f64,
+    pub strat: T6strat,
+    /// Milestone levels, in `MILESTONES` order.
+    pub milestones: [u32; 1],
+    /// Derive the milestones from rho and tau instead of keeping the given ones.
+    pub derive_milestones: bool,
 }
 
 impl Copy for T6data {}
@@ -95,17 +147,23 @@ pub struct T6state {
     pub q: f64,
     pub r: f64,
     pub tol: f64,
+    /// Milestone levels, kept instead of derived.
+    pub milestones: [u32; 1],
 }
 
 pub struct T6 {
     data: TheoryData,
-    t6data: T6data,
+    pub t6data: T6data,
     goal: f64,
     rho: f64,
     maxrho: f64,
     multiplier: f64,
     q: f64,
     r: f64,
+    /// Magnitude of the integration constant C, rho = integral - C.
+    c: f64,
+    /// C is negative, rho = integral + |C|.
+    c_neg: bool,
     vars: T6vars,
     varbuys: Vec<VarBuy>,
 
@@ -127,6 +185,9 @@ impl T6 {
                 scale_start: -2.,
                 scale_end: 2.,
                 tol: 1.,
+                strat: T6strat::Ratio,
+                milestones: MILESTONES.max_levels,
+                derive_milestones: true,
             },
             goal: goal,
             rho: 0.,
@@ -134,6 +195,8 @@ impl T6 {
             multiplier: 0.,
             q: 0.,
             r: 0.,
+            c: 0.,
+            c_neg: false,
             vars: T6vars::init(),
 
             varbuys: Vec::new(),
@@ -151,10 +214,14 @@ impl T6 {
             t6.q = state.q;
             t6.r = state.r;
             t6.t6data.tol = state.tol;
+            t6.t6data.milestones = state.milestones;
+            t6.t6data.derive_milestones = false;
         }
 
         t6.rho = t6.data.rho;
         t6.multiplier = t6.get_multiplier(t6.data.tau, t6.data.students);
+        t6.update_milestones();
+        t6.rebase();
 
         t6
     }
@@ -169,6 +236,8 @@ impl T6 {
             multiplier: self.multiplier,
             q: self.q,
             r: self.r,
+            c: self.c,
+            c_neg: self.c_neg,
             vars: T6vars::init(),
 
             varbuys: self.varbuys.clone(),
@@ -190,6 +259,9 @@ impl T6 {
         new.vars.r2.set(self.vars.r2.level);
         new.vars.c5.set(self.vars.c5.level);*/
 
+        // the fork can happen in the middle of a buy, before this one re-bases
+        new.rebase();
+
         new
     }
 
@@ -404,25 +476,49 @@ impl T6 {
         }
     }
 
+    /// Milestones reached with the max of rho and the last pub's rho. They change the
+    /// integral, not rho, so the integration constant is rebased.
+    fn update_milestones(&mut self) {
+        if !self.t6data.derive_milestones {
+            return;
+        }
+        let milestones = MILESTONES.at(self.maxrho.max(self.data.tau));
+        if milestones != self.t6data.milestones {
+            self.t6data.milestones = milestones;
+            self.rebase();
+        }
+    }
+
     fn calc_integral(&self) -> f64 {
-        let term1: f64 = self.vars.c1.value * 1.15 + self.vars.c2.value + self.q + self.r;
+        let term1: f64 = self.vars.c1.value * (1. + 0.05 * self.t6data.milestones[0] as f64)
+            + self.vars.c2.value
+            + self.q
+            + self.r;
         let term2: f64 = self.vars.c5.value + self.q + 2. * self.r - 2f64.log10();
 
         self.multiplier + log10add(term1, term2)
     }
 
+    /// Sets the integration constant so that rho is unchanged by the last purchases.
+    /// Buying c1, c2 or c5 scales the integral, and spending rho lowers it, as in the game.
+    fn rebase(&mut self) {
+        let integral: f64 = self.calc_integral();
+        // at the start of a pub the integral can still be below rho, C is then negative
+        self.c_neg = integral < self.rho;
+        self.c = log10sub(integral, self.rho);
+    }
+
     fn tick(&mut self) {
         let logdt: f64 = self.dt.log10();
 
-        let mut c: f64 = log10sub(self.calc_integral(), self.rho);
-
         self.q = log10add(self.q, self.vars.q1.value + self.vars.q2.value + logdt);
         self.r = log10add(self.r, self.vars.r1.value + self.vars.r2.value + logdt - 3.);
 
-        let newrho: f64 = self.calc_integral();
-
-        c = newrho.min(c);
-        self.rho = log10sub(newrho, c);
+        self.rho = if self.c_neg {
+            log10add(self.calc_integral(), self.c)
+        } else {
+            log10sub(self.calc_integral(), self.c)
+        };
         self.maxrho = self.maxrho.max(self.rho);
 
         self.t += self.dt / 1.5;
@@ -431,6 +527,7 @@ impl T6 {
 
     pub fn simulate(&mut self) -> SimRes {
         while self.maxrho < self.goal {
+            self.update_milestones();
             self.tick();
             self.buy();
         }
@@ -449,34 +546,50 @@ impl T6 {
         }
     }
 
-    fn _buy_noc1234(&mut self) {
+    fn buy_noc1234(&mut self) {
         let mut cost: f64;
-        let variables = [(6, "c5"), (3, "r2"), (2, "r1"), (1, "q2"), (0, "q1")];
+        let names = ["c5", "r2", "r1", "q2", "q1"];
 
-        for (id, name) in variables {
-            cost = self.vars.get(id).get_cost();
+        for (var, name) in self.vars.iter_noc12_r().into_iter().zip(names) {
+            cost = var.get_cost();
             while self.rho > cost {
                 self.rho = log10sub(self.rho, cost);
-                self.vars.getm(id).buy();
+                var.buy();
                 if self.rho > self.data.tau - 5. {
                     self.varbuys.push(VarBuy {
                         symb: String::from(name),
-                        lvl: self.vars.get(id).get_level(),
+                        lvl: var.get_level(),
                         t: self.t,
                     });
                 }
-                cost = self.vars.get(id).get_cost();
+                cost = var.get_cost();
             }
         }
+
+        self.rebase();
     }
 
     fn buy(&mut self) {
         let mut cost: f64;
         let mut coast_eval: BuyEval;
         let mut ratio_eval: BuyEval;
-        let variables: [(usize, &str); 5] = [(6, "c5"), (3, "r2"), (2, "r1"), (1, "q2"), (0, "q1")];
+        let variables: &[(usize, &str)] = match self.t6data.strat {
+            T6strat::NoC1234 => return self.buy_noc1234(),
+            T6strat::C125 => &[
+                (6, "c5"),
+                (5, "c2"),
+                (4, "c1"),
+                (3, "r2"),
+                (2, "r1"),
+                (1, "q2"),
+                (0, "q1"),
+            ],
+            T6strat::C5 | T6strat::Ratio => {
+                &[(6, "c5"), (3, "r2"), (2, "r1"), (1, "q2"), (0, "q1")]
+            }
+        };
 
-        for (id, name) in variables {
+        for &(id, name) in variables {
             if self.t6data.skip[id] {
                 continue;
             }
@@ -488,7 +601,10 @@ impl T6 {
 
             while self.rho > cost {
                 coast_eval = self.eval_coast(id, cost);
-                ratio_eval = self.eval_ratio(id);
+                ratio_eval = match self.t6data.strat {
+                    T6strat::Ratio => self.eval_ratio(id),
+                    _ => BuyEval::BUY,
+                };
 
                 if coast_eval != BuyEval::SKIP {
                     if ratio_eval == BuyEval::SKIP {
@@ -549,5 +665,7 @@ impl T6 {
                 }
             }
         }
+
+        self.rebase();
     }
 }
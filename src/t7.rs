@@ -1,12 +1,70 @@
+use std::str::FromStr;
+
 use crate::s;
 use crate::utils::*;
 
+/// Milestone tree: c1 exponent, c3 term, c4 term, c5 term and c6 term.
+/// c1 starts at 0, so the c3 term comes first to get rho1 growing.
+pub const MILESTONES: Milestones<5> = Milestones {
+    points: &[25., 50., 75., 100., 125., 150., 175.],
+    max_levels: [3, 1, 1, 1, 1],
+    order: [1, 2, 3, 4, 0],
+};
+
+/// Width (log10) of the window above the coasting threshold where the fork strategy forks.
+const FORK_WINDOW: f64 = 0.5;
+
+/// Substeps after which a tick takes the rest of its dt at once. 10% steps cover
+/// about 2700 orders of magnitude before that, so it only guards against a zero rho.
+const MAX_SUBSTEPS: u32 = 1 << 16;
+
+/// Purchase strategies for T7.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum T7strat {
+    /// Buys gated on the distance to c6's cost, coasting near the goal.
+    Ratio,
+    /// Only c6, q1 and q2 once past the early game.
+    C6,
+    /// Ratio with forks on each purchase close to the coasting threshold.
+    Fork,
+    /// Pure autobuy of every unlocked variable.
+    Autobuy,
+}
+
+impl T7strat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            T7strat::Ratio => "ratio",
+            T7strat::C6 => "c6",
+            T7strat::Fork => "fork",
+            T7strat::Autobuy => "autobuy",
+        }
+    }
+}
+
+impl FromStr for T7strat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &s.to_lowercase()[..] {
+            "ratio" => Ok(T7strat::Ratio),
+            "c6" => Ok(T7strat::C6),
+            "fork" => Ok(T7strat::Fork),
+            "autobuy" => Ok(T7strat::Autobuy),
+            _ => Err(format!("Unknown T7 strategy: {s}")),
+        }
+    }
+}
+
 struct T7vars {
     q1: Variable<FirstFreeCost<ExponentialCost>, StepwiseValue>,
     c3: Variable<ExponentialCost, ExponentialValue>,
     c4: Variable<ExponentialCost, ExponentialValue>,
     c5: Variable<ExponentialCost, ExponentialValue>,
     c6: Variable<ExponentialCost, ExponentialValue>,
+    q2: Variable<ExponentialCost, ExponentialValue>,
+    c1: Variable<ExponentialCost, StepwiseValue>,
+    c2: Variable<ExponentialCost, ExponentialValue>,
 }
 
 impl T7vars {
@@ -34,6 +92,9 @@ impl T7vars {
                 ExponentialCost::new(100., 2.81),
                 ExponentialValue::new(2.),
             ),
+            q2: Variable::new(ExponentialCost::new(1e10, 1e20), ExponentialValue::new(2.)),
+            c1: Variable::new(ExponentialCost::new(10., 16.), StepwiseValue::new(2., 10)),
+            c2: Variable::new(ExponentialCost::new(40., 1e3), ExponentialValue::new(2.)),
         }
     }
 
@@ -43,7 +104,10 @@ impl T7vars {
             1 => &mut self.c3,
             2 => &mut self.c4,
             3 => &mut self.c5,
-            _ => &mut self.c6
+            4 => &mut self.c6,
+            5 => &mut self.q2,
+            6 => &mut self.c1,
+            _ => &mut self.c2,
         }
     }
 
@@ -53,11 +117,14 @@ impl T7vars {
             1 => &self.c3,
             2 => &self.c4,
             3 => &self.c5,
-            _ => &self.c6
+            4 => &self.c6,
+            5 => &self.q2,
+            6 => &self.c1,
+            _ => &self.c2,
         }
     }
 
-    fn set(&mut self, lvls: [u32; 5]) {
+    fn set(&mut self, lvls: [u32; 8]) {
         for (i, level) in lvls.iter().enumerate() {
             self.getm(i).set(*level);
         }
@@ -66,14 +133,20 @@ impl T7vars {
 
 #[derive(Clone)]
 pub struct T7data {
+    caps: [u32; 8],
     pub do_coasting: bool,
+    /// Milestone levels, in `MILESTONES` order.
+    pub milestones: [u32; 5],
+    /// Derive the milestones from rho and tau instead of keeping the given ones.
+    pub derive_milestones: bool,
+    pub strat: T7strat,
 }
 
 impl Copy for T7data {}
 
 pub struct T7state {
-    pub levels: [u32; 5],
-    pub rho2: f64
+    pub levels: [u32; 8],
+    pub rho2: f64,
 }
 
 pub struct T7 {
@@ -82,8 +155,6 @@ pub struct T7 {
     pub goal: f64,
     rho: f64,
     rho2: f64,
-    drho13: f64,
-    drho23: f64,
     maxrho: f64,
     multiplier: f64,
     vars: T7vars,
@@ -101,12 +172,16 @@ impl T7 {
     pub fn new(data: TheoryData, goal: f64, state: Option<T7state>) -> Self {
         let mut t7: T7 = T7 {
             data: data,
-            t7data: T7data { do_coasting: true },
+            t7data: T7data {
+                caps: [u32::MAX; 8],
+                do_coasting: true,
+                milestones: MILESTONES.max_levels,
+                derive_milestones: true,
+                strat: T7strat::Ratio,
+            },
             goal: goal,
             rho: 0.,
             rho2: 0.,
-            drho13: 0.,
-            drho23: 0.,
             maxrho: 0.,
             multiplier: 0.,
             vars: T7vars::init(),
@@ -141,8 +216,6 @@ impl T7 {
             goal: self.goal,
             rho: self.rho,
             rho2: self.rho2,
-            drho13: self.drho13,
-            drho23: self.drho23,
             maxrho: self.maxrho,
             multiplier: self.multiplier,
             vars: T7vars::init(),
@@ -155,59 +228,73 @@ impl T7 {
             best_res: SimRes::default(),
         };
 
-        for i in 0..5 {
+        for i in 0..8 {
             new.vars.getm(i).set(self.vars.get(i).get_level())
         }
 
         new
     }
 
+    /// Milestones reached with the max of rho and the last pub's rho.
+    fn update_milestones(&mut self) {
+        if self.t7data.derive_milestones {
+            let rho = self.maxrho.max(self.data.tau);
+            self.t7data.milestones = MILESTONES.at(rho);
+        }
+    }
+
     fn get_multiplier(&self, tau: f64, sigma: u32) -> f64 {
         tau * 0.152 + 3. * (sigma as f64 / 20.).log10()
     }
 
+    fn eval_coast_one(&self, dist: f64, lbound: f64, ubound: f64) -> BuyEval {
+        if dist > ubound {
+            BuyEval::BUY
+        } else if dist > lbound {
+            BuyEval::FORK
+        } else {
+            BuyEval::SKIP
+        }
+    }
+
     fn eval_coast(&self, id: usize, cost: f64) -> BuyEval {
         let dist: f64 = self.goal - cost;
         if dist > 1.5 {
             return BuyEval::BUY;
         }
-        match id {
-            0 | 3 => {
-                if dist < 8f64.log10() {
-                    BuyEval::SKIP
-                } else {
-                    BuyEval::BUY
-                }
-            }
-            1 | 2 => {
-                if dist < 20f64.log10() {
-                    BuyEval::SKIP
-                } else {
-                    BuyEval::BUY
-                }
-            }
-            4 => {
-                if dist < 2f64.log10() {
-                    BuyEval::SKIP
-                } else {
-                    BuyEval::BUY
-                }
-            }
-            _ => BuyEval::SKIP,
+        let lbound: f64 = match id {
+            0 | 3 | 5 => 8f64.log10(),
+            1 | 2 | 6 | 7 => 20f64.log10(),
+            4 => 2f64.log10(),
+            _ => return BuyEval::SKIP,
+        };
+        if self.t7data.strat == T7strat::Fork {
+            self.eval_coast_one(dist, lbound, lbound + FORK_WINDOW)
+        } else if dist < lbound {
+            BuyEval::SKIP
+        } else {
+            BuyEval::BUY
         }
     }
 
     fn eval_ratio(&self, id: usize, cost: f64) -> BuyEval {
+        // The ratios are against c6, which stays at its base cost until its term is unlocked.
+        if self.t7data.milestones[4] == 0 {
+            return BuyEval::BUY;
+        }
+        if self.t7data.strat == T7strat::C6 && !matches!(id, 0 | 4 | 5) {
+            return BuyEval::SKIP;
+        }
         let dist: f64 = self.vars.c6.cost - cost;
         match id {
-            0 | 3 => {
+            0 | 3 | 5 => {
                 if dist < 4f64.log10() {
                     BuyEval::SKIP
                 } else {
                     BuyEval::BUY
                 }
             }
-            1 | 2 => {
+            1 | 2 | 6 | 7 => {
                 if dist < 1. {
                     BuyEval::SKIP
                 } else {
@@ -219,15 +306,65 @@ impl T7 {
         }
     }
 
+    fn get_variable_conditions(&self, id: usize) -> bool {
+        match id {
+            0 | 5..=7 => true,
+            1 => self.t7data.milestones[1] >= 1,
+            2 => self.t7data.milestones[2] >= 1,
+            3 => self.t7data.milestones[3] >= 1,
+            4 => self.t7data.milestones[4] >= 1,
+            _ => false,
+        }
+    }
+
+    /// log10 of the rho1 and rho2 rates, without the q1 q2 factor.
+    fn get_drho(&self) -> (f64, f64) {
+        let ms = self.t7data.milestones;
+        let drho11 = self.vars.c1.value * (1. + 0.05 * ms[0] as f64) + self.vars.c2.value;
+        let mut drho1 = drho11;
+        let mut drho2 = f64::NEG_INFINITY;
+
+        if ms[1] >= 1 {
+            drho1 = log10add(drho1, 1.5f64.log10() + self.vars.c3.value + self.rho / 2.);
+        }
+        if ms[2] >= 1 {
+            drho2 = self.vars.c4.value;
+        }
+        if ms[3] >= 1 {
+            drho2 = log10add(drho2, 1.5f64.log10() + self.vars.c5.value + self.rho2 / 2.);
+        }
+        if ms[4] >= 1 {
+            let drho13 = 0.5f64.log10() + self.vars.c6.value + self.rho2 / 2. - self.rho / 2.;
+            let drho23 = 0.5f64.log10() + self.vars.c6.value + self.rho / 2. - self.rho2 / 2.;
+            drho1 = log10add(drho1, drho13);
+            drho2 = log10add(drho2, drho23);
+        }
+
+        (drho1, drho2)
+    }
+
+    /// The sqrt terms of c3, c5 and c6 are stiff while rho1 or rho2 is small.
+    /// The tick is split into substeps sized from the current rates, so that each
+    /// grows rho1 and rho2 by at most 10%, and a single step once that is slow enough.
     fn tick(&mut self) {
-        let drho12 = 1.5f64.log10() + self.vars.c3.value + self.rho / 2.;
-        let drho22 = 1.5f64.log10() + self.vars.c5.value + self.rho2 / 2.;
-        self.drho13 = (0.5f64.log10() + self.vars.c6.value + self.rho2 / 2. - self.rho / 2.).min(self.drho13 + 2.).min(self.rho + 2.);
-        self.drho23 = (0.5f64.log10() + self.vars.c6.value + self.rho / 2. - self.rho2 / 2.).min(self.drho23 + 2.).min(self.rho2 + 2.);
-        let dtq1bonus = self.dt.log10() + self.vars.q1.value + self.multiplier;
+        let bonus: f64 = self.vars.q1.value + self.vars.q2.value + self.multiplier;
+        let mut left: f64 = self.dt;
+        let mut steps: u32 = 0;
+
+        while left > 0. {
+            let (drho1, drho2) = self.get_drho();
+            let mut h: f64 = left;
+            if steps < MAX_SUBSTEPS {
+                let growth = (drho1 - self.rho).max(drho2 - self.rho2) + bonus;
+                h = h.min(10f64.powf(-1. - growth));
+            }
 
-        self.rho = log10add(self.rho, dtq1bonus + log10add(drho12, self.drho13));
-        self.rho2 = log10add(self.rho2, dtq1bonus + log10add(drho22, self.drho23));
+            let logdt: f64 = h.log10();
+            self.rho = log10add(self.rho, bonus + drho1 + logdt);
+            self.rho2 = log10add(self.rho2, bonus + drho2 + logdt);
+            left -= h;
+            steps += 1;
+        }
 
         self.maxrho = self.maxrho.max(self.rho);
 
@@ -237,6 +374,7 @@ impl T7 {
 
     pub fn simulate(&mut self) -> SimRes {
         while self.maxrho < self.goal {
+            self.update_milestones();
             self.tick();
             self.buy();
         }
@@ -257,13 +395,18 @@ impl T7 {
     fn buy(&mut self) {
         let mut cost: f64;
         let mut eval: BuyEval;
-        let names = ["c6", "c5", "c4", "c3", "q1"];
-        let ids: [usize; 5] = [4, 3, 2, 1, 0];
-
-        for i in 0..5 {
+        let names = ["c6", "c5", "c4", "c3", "c2", "c1", "q2", "q1"];
+        let ids: [usize; 8] = [4, 3, 2, 1, 7, 6, 5, 0];
+
+        for i in 0..8 {
+            if !self.get_variable_conditions(ids[i])
+                || self.vars.get(ids[i]).get_level() >= self.t7data.caps[ids[i]]
+            {
+                continue;
+            }
             cost = self.vars.get(ids[i]).get_cost();
             while self.rho > cost {
-                eval = if cost < self.data.tau - 50. {
+                eval = if cost < self.data.tau - 50. || self.t7data.strat == T7strat::Autobuy {
                     BuyEval::BUY
                 } else {
                     let ev = self.eval_ratio(ids[i], cost);
@@ -274,7 +417,16 @@ impl T7 {
                         ev
                     }
                 };
-                if eval == BuyEval::BUY {
+                if eval == BuyEval::FORK {
+                    let mut fork: T7 = self.fork();
+                    fork.t7data.caps[ids[i]] = self.vars.get(ids[i]).get_level();
+                    let res: SimRes = fork.simulate();
+                    if res.t < self.best_res.t {
+                        self.best_res.t = res.t;
+                        self.best_res.var_buys = res.var_buys;
+                    }
+                }
+                if eval != BuyEval::SKIP {
                     self.rho = log10sub(self.rho, cost);
                     self.vars.getm(ids[i]).buy();
                     cost = self.vars.get(ids[i]).get_cost();
@@ -292,4 +444,4 @@ impl T7 {
             }
         }
     }
-}
\ No newline at end of file
+}
@@ -30,6 +30,9 @@ pub fn strtolog10(s: &str) -> f64 {
 pub fn log10add(a: f64, b: f64) -> f64 {
     let max: f64 = a.max(b);
     let min: f64 = a.min(b);
+    if max == f64::NEG_INFINITY {
+        return max;
+    }
     let whole1: f64 = max.floor();
     let frac1: f64 = 10f64.powf(max - whole1);
     let whole2: f64 = min.floor();
@@ -314,6 +317,68 @@ impl<T: Cost, U: Value> VariableTrait for Variable<T, U> {
     }
 }
 
+// Milestones
+
+/// Milestone tree of a theory. A point is earned at each rho (log10) in `points`,
+/// and points are spent in `order`, each milestone filled up to its max level first.
+/// Theories without modelled thresholds leave `points` empty and take levels as input.
+#[derive(Debug, Clone, Copy)]
+pub struct Milestones<const N: usize> {
+    pub points: &'static [f64],
+    pub max_levels: [u32; N],
+    pub order: [usize; N],
+}
+
+impl<const N: usize> Milestones<N> {
+    /// Number of points earned at rho.
+    pub fn points_at(&self, rho: f64) -> u32 {
+        self.points.iter().filter(|point| rho >= **point).count() as u32
+    }
+
+    /// Rho of the next point after rho, f64::MAX once every point is earned.
+    pub fn next_cost(&self, rho: f64) -> f64 {
+        self.points
+            .iter()
+            .find(|point| rho < **point)
+            .copied()
+            .unwrap_or(f64::MAX)
+    }
+
+    /// Points needed to max every milestone.
+    pub fn total(&self) -> u32 {
+        self.max_levels.iter().sum()
+    }
+
+    /// Milestone levels with `points` spent in the given order.
+    pub fn allocate(&self, mut points: u32, order: &[usize; N]) -> [u32; N] {
+        let mut levels = [0; N];
+        for &i in order {
+            let lvl = points.min(self.max_levels[i]);
+            levels[i] = lvl;
+            points -= lvl;
+        }
+        levels
+    }
+
+    /// Milestone levels at rho, spent in the default order.
+    pub fn at(&self, rho: f64) -> [u32; N] {
+        self.allocate(self.points_at(rho), &self.order)
+    }
+
+    /// Rejects levels above a milestone's max level.
+    pub fn validate(&self, levels: [u32; N]) -> Result<[u32; N], String> {
+        for (i, (lvl, max)) in levels.iter().zip(self.max_levels).enumerate() {
+            if *lvl > max {
+                return Err(format!(
+                    "milestone {} level {lvl} is above its max level {max}",
+                    i + 1
+                ));
+            }
+        }
+        Ok(levels)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct TheoryData {
     pub tau: f64,
@@ -381,3 +446,54 @@ impl Default for SimRes {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TREE: Milestones<3> = Milestones {
+        points: &[10., 20., 30., 40.],
+        max_levels: [1, 2, 1],
+        order: [1, 0, 2],
+    };
+
+    #[test]
+    fn points_at_counts_reached_points() {
+        assert_eq!(TREE.points_at(0.), 0);
+        assert_eq!(TREE.points_at(10.), 1);
+        assert_eq!(TREE.points_at(29.9), 2);
+        assert_eq!(TREE.points_at(1000.), 4);
+    }
+
+    #[test]
+    fn next_cost_is_next_point() {
+        assert_eq!(TREE.next_cost(0.), 10.);
+        assert_eq!(TREE.next_cost(10.), 20.);
+        assert_eq!(TREE.next_cost(40.), f64::MAX);
+    }
+
+    #[test]
+    fn allocate_fills_in_order() {
+        assert_eq!(TREE.total(), 4);
+        assert_eq!(TREE.allocate(0, &TREE.order), [0, 0, 0]);
+        assert_eq!(TREE.allocate(1, &TREE.order), [0, 1, 0]);
+        assert_eq!(TREE.allocate(3, &TREE.order), [1, 2, 0]);
+        assert_eq!(TREE.allocate(4, &[2, 0, 1]), [1, 2, 1]);
+        assert_eq!(TREE.allocate(2, &[2, 0, 1]), [1, 0, 1]);
+    }
+
+    #[test]
+    fn at_allocates_points_at_rho() {
+        assert_eq!(TREE.at(5.), [0, 0, 0]);
+        assert_eq!(TREE.at(25.), [0, 2, 0]);
+        assert_eq!(TREE.at(35.), [1, 2, 0]);
+        assert_eq!(TREE.at(50.), [1, 2, 1]);
+    }
+
+    #[test]
+    fn validate_rejects_levels_above_max() {
+        assert_eq!(TREE.validate([1, 2, 1]), Ok([1, 2, 1]));
+        assert!(TREE.validate([0, 3, 0]).is_err());
+        assert!(TREE.validate([2, 0, 0]).is_err());
+    }
+}